@@ -0,0 +1,184 @@
+use super::super::util;
+use super::command::{borg_command, execute_borg};
+use super::errors::CheckError;
+use super::passphrase::{PassphraseArgs, PassphraseTrait};
+use super::remote::RemoteArgs;
+use super::BorgTrait;
+use clap::Parser;
+
+// Struct for managing the necessary arguments for checking the
+// consistency of a repository and its archives.
+#[derive(Debug, Clone, Parser)]
+pub struct CheckArgs {
+    repository: String,
+    #[arg(short, long)]
+    passphrase: Option<String>,
+    #[command(flatten)]
+    passphrase_source: PassphraseArgs,
+    #[command(flatten)]
+    remote: RemoteArgs,
+    #[arg(long, default_value_t = false, conflicts_with = "archives_only")]
+    repository_only: bool,
+    #[arg(long, default_value_t = false)]
+    archives_only: bool,
+    #[arg(long, default_value_t = false, conflicts_with = "repository_only")]
+    verify_data: bool,
+    #[arg(long, default_value_t = false)]
+    repair: bool,
+    #[arg(long, default_value_t = false)]
+    confirm_repair: bool,
+}
+
+impl BorgTrait for CheckArgs {
+    fn repository(&self) -> String {
+        self.repository.to_owned()
+    }
+
+    fn passphrase(&self) -> Option<String> {
+        self.passphrase.to_owned()
+    }
+
+    fn remote(&self) -> RemoteArgs {
+        self.remote.to_owned()
+    }
+}
+
+impl PassphraseTrait for CheckArgs {
+    fn passphrase_source(&self) -> &PassphraseArgs {
+        &self.passphrase_source
+    }
+
+    fn with_passphrase(self, passphrase: String) -> Self {
+        CheckArgs {
+            passphrase: Some(passphrase),
+            ..self
+        }
+    }
+}
+
+impl CheckArgs {
+    // Converts the selected check modes into borg `check` arguments.
+    fn to_args(&self) -> Vec<String> {
+        let flags = [
+            ("--repository-only", self.repository_only),
+            ("--archives-only", self.archives_only),
+            ("--verify-data", self.verify_data),
+            ("--repair", self.repair),
+        ];
+        let mut args = vec!["check".to_owned()];
+        for (flag, enabled) in flags {
+            if enabled {
+                args.push(flag.to_owned());
+            }
+        }
+        args.push(self.repository.to_owned());
+        args
+    }
+}
+
+// The outcome of a consistency check, including any problems borg reported.
+#[derive(Debug, Clone, Default)]
+pub struct CheckResult {
+    pub passed: bool,
+    pub problems: Vec<String>,
+}
+
+pub fn display_check_info(check_result: &CheckResult) {
+    for problem in check_result.problems.iter() {
+        util::log_print(problem, util::LogLevel::Warn);
+    }
+    if check_result.passed {
+        util::log_print("Check passed, no problems found", util::LogLevel::Info);
+    } else {
+        util::log_print("Check failed, problems found", util::LogLevel::Warn);
+    }
+}
+
+// The entrypoint for the `check` module where a variable of type
+// CheckArgs is passed containing the repository and the checks to run.
+//
+// Repairing a repository can lose data so `--repair` is refused unless
+// `--confirm-repair` is also given, which then answers borg's prompt.
+//
+// Borg exits with a warning when problems are found, so the check only
+// passes when borg exits cleanly.
+pub fn check_repository(check_args: &CheckArgs) -> Result<CheckResult, CheckError> {
+    if check_args.repair && !check_args.confirm_repair {
+        return Err(CheckError::RepairNotConfirmed);
+    }
+
+    let mut command = borg_command(
+        check_args.to_args(),
+        check_args.passphrase(),
+        &check_args.common_options(),
+    );
+    if check_args.repair {
+        command.env("BORG_CHECK_I_KNOW_WHAT_I_AM_DOING", "YES");
+    }
+
+    let output = execute_borg(command).map_err(CheckError::CheckCommandError)?;
+    let problems = output
+        .messages
+        .iter()
+        .filter(|m| matches!(m.level_name.as_deref(), Some("WARNING") | Some("ERROR")))
+        .filter_map(|m| m.message.to_owned())
+        .collect();
+
+    Ok(CheckResult {
+        passed: output.exit_code == 0,
+        problems,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup_check_args() -> CheckArgs {
+        CheckArgs {
+            repository: "repository".to_owned(),
+            passphrase: Some("passphrase".to_owned()),
+            passphrase_source: PassphraseArgs::default(),
+            remote: RemoteArgs::default(),
+            repository_only: false,
+            archives_only: false,
+            verify_data: false,
+            repair: false,
+            confirm_repair: false,
+        }
+    }
+
+    #[test]
+    fn test_get_repository() {
+        let check_args = setup_check_args();
+        assert_eq!(check_args.repository(), "repository");
+    }
+
+    #[test]
+    fn test_get_passphrase() {
+        let check_args = setup_check_args();
+        assert_eq!(check_args.passphrase(), Some("passphrase".to_owned()));
+    }
+
+    #[test]
+    fn test_to_args() {
+        let mut check_args = setup_check_args();
+        assert_eq!(check_args.to_args(), vec!["check", "repository"]);
+
+        check_args.archives_only = true;
+        check_args.verify_data = true;
+        assert_eq!(
+            check_args.to_args(),
+            vec!["check", "--archives-only", "--verify-data", "repository"]
+        );
+    }
+
+    #[test]
+    fn test_check_repository_repair_fail() {
+        let mut check_args = setup_check_args();
+        check_args.repair = true;
+
+        let result = check_repository(&check_args);
+        assert!(matches!(result, Err(CheckError::RepairNotConfirmed)));
+    }
+}
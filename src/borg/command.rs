@@ -0,0 +1,217 @@
+use super::errors::CommandError;
+use borgbackup::common::CommonOptions;
+use serde::Deserialize;
+use std::process::Command;
+
+// A single JSON log line written by borg to the `stderr` when `--log-json` is used.
+//
+// Only `log_message` lines carry a message, other types such as progress
+// updates are parsed with their missing fields set to None.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogMessage {
+    #[serde(rename = "levelname")]
+    pub level_name: Option<String>,
+    pub name: Option<String>,
+    pub message: Option<String>,
+    #[serde(rename = "msgid")]
+    pub msg_id: Option<String>,
+}
+
+// The result of running borg directly, used for commands the BorgBackup
+// crate doesn't support (or doesn't expose all options for).
+#[derive(Debug, Clone)]
+pub struct BorgOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub messages: Vec<LogMessage>,
+}
+
+impl BorgOutput {
+    // Returns the messages emitted by the given borg logger, e.g. `borg.output.list`.
+    pub fn messages_from(&self, name: &str) -> Vec<String> {
+        self.messages
+            .iter()
+            .filter(|m| m.name.as_deref() == Some(name))
+            .filter_map(|m| m.message.to_owned())
+            .collect()
+    }
+
+    // Returns the warnings borg logged, such as files that couldn't be read
+    // while creating an archive.
+    pub fn warnings(&self) -> Vec<String> {
+        self.messages
+            .iter()
+            .filter(|m| m.level_name.as_deref() == Some("WARNING"))
+            .filter_map(|m| m.message.to_owned())
+            .collect()
+    }
+}
+
+// Creates a borg Command with JSON logging enabled, the CommonOptions
+// applied and the passphrase passed through `BORG_PASSPHRASE`.
+//
+// The Command is returned so the caller can set any additional
+// environment variables or a working directory before executing it.
+pub fn borg_command(
+    args: Vec<String>,
+    passphrase: Option<String>,
+    common_options: &CommonOptions,
+) -> Command {
+    let local_path = common_options.local_path.as_deref().unwrap_or("borg");
+    let mut command = Command::new(local_path);
+    command.arg("--log-json");
+
+    if let Some(rsh) = &common_options.rsh {
+        command.args(["--rsh", rsh]);
+    }
+    if let Some(remote_path) = &common_options.remote_path {
+        command.args(["--remote-path", remote_path]);
+    }
+    if let Some(upload_ratelimit) = common_options.upload_ratelimit {
+        command.args(["--upload-ratelimit", &upload_ratelimit.to_string()]);
+    }
+    if let Some(passphrase) = passphrase {
+        command.env("BORG_PASSPHRASE", passphrase);
+    }
+
+    command.args(args);
+    command
+}
+
+// Maps the message ID of a borg error to a CommandError, falling back
+// to the message itself when the ID isn't one we handle.
+fn parse_error_message(message: &LogMessage) -> CommandError {
+    match message.msg_id.as_deref() {
+        Some("PassphraseWrong") => CommandError::PassphraseWrong,
+        Some("Repository.DoesNotExist") => CommandError::RepositoryDoesNotExist,
+        Some("Archive.DoesNotExist") => CommandError::ArchiveDoesNotExist,
+        Some("Archive.AlreadyExists") => CommandError::ArchiveAlreadyExists,
+        Some("LockError" | "LockErrorT" | "LockFailed" | "LockTimeout") => CommandError::LockFailed,
+        Some("ConnectionClosed" | "ConnectionClosedWithHint") => CommandError::ConnectionClosed,
+        _ => CommandError::Unknown(message.message.to_owned().unwrap_or_default()),
+    }
+}
+
+// Executes a Command created by `borg_command` and parses its output.
+//
+// Borg exits with 0 on success and 1 on warnings, both of which are treated
+// as success. Any other exit code is converted into a CommandError based on
+// the error messages borg logged.
+pub fn execute_borg(mut command: Command) -> Result<BorgOutput, CommandError> {
+    let output = command.output().map_err(CommandError::CommandFailed)?;
+    let exit_code = match output.status.code() {
+        Some(n) => n,
+        None => return Err(CommandError::TerminatedBySignal),
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut unparsed = Vec::new();
+    let mut messages = Vec::new();
+    for line in stderr.lines() {
+        match serde_json::from_str::<LogMessage>(line) {
+            Ok(n) => messages.push(n),
+            Err(_) => unparsed.push(line.to_owned()),
+        }
+    }
+
+    if exit_code > 1 {
+        let error = messages
+            .iter()
+            .rev()
+            .find(|m| matches!(m.level_name.as_deref(), Some("ERROR") | Some("CRITICAL")));
+        return Err(match error {
+            Some(n) => parse_error_message(n),
+            None => CommandError::Unknown(unparsed.join("\n")),
+        });
+    }
+
+    Ok(BorgOutput {
+        exit_code,
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        messages,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup_log_message(msg_id: Option<&str>) -> LogMessage {
+        LogMessage {
+            level_name: Some("ERROR".to_owned()),
+            name: Some("borg.archiver".to_owned()),
+            message: Some("message".to_owned()),
+            msg_id: msg_id.map(|n| n.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_borg_command() {
+        let common_options = CommonOptions {
+            rsh: Some("ssh -i key".to_owned()),
+            ..Default::default()
+        };
+        let command = borg_command(
+            vec!["list".to_owned(), "repository".to_owned()],
+            Some("passphrase".to_owned()),
+            &common_options,
+        );
+
+        assert_eq!(command.get_program(), "borg");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            ["--log-json", "--rsh", "ssh -i key", "list", "repository"]
+        );
+        let envs: Vec<_> = command.get_envs().collect();
+        assert_eq!(envs.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_error_message() {
+        let result1 = parse_error_message(&setup_log_message(Some("PassphraseWrong")));
+        assert!(matches!(result1, CommandError::PassphraseWrong));
+
+        let result2 = parse_error_message(&setup_log_message(Some("Repository.DoesNotExist")));
+        assert!(matches!(result2, CommandError::RepositoryDoesNotExist));
+
+        let result3 = parse_error_message(&setup_log_message(Some("LockTimeout")));
+        assert!(matches!(result3, CommandError::LockFailed));
+
+        let result4 = parse_error_message(&setup_log_message(Some("ConnectionClosed")));
+        assert!(matches!(result4, CommandError::ConnectionClosed));
+
+        match parse_error_message(&setup_log_message(None)) {
+            CommandError::Unknown(n) => assert_eq!(n, "message"),
+            _ => panic!("Expected an unknown error"),
+        }
+    }
+
+    #[test]
+    fn test_messages_from() {
+        let mut message = setup_log_message(None);
+        message.name = Some("borg.output.list".to_owned());
+        let output = BorgOutput {
+            exit_code: 0,
+            stdout: String::new(),
+            messages: vec![message, setup_log_message(None)],
+        };
+        assert_eq!(output.messages_from("borg.output.list"), vec!["message"]);
+    }
+
+    #[test]
+    fn test_warnings() {
+        let mut message = setup_log_message(None);
+        message.level_name = Some("WARNING".to_owned());
+        message.message = Some("file changed while we backed it up".to_owned());
+        let output = BorgOutput {
+            exit_code: 1,
+            stdout: String::new(),
+            messages: vec![message, setup_log_message(None)],
+        };
+        assert_eq!(
+            output.warnings(),
+            vec!["file changed while we backed it up"]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use super::super::util;
+use super::command::{borg_command, execute_borg};
+use super::errors::CommandError;
+use super::remote::RemoteArgs;
+use super::{BorgTrait, CompactTrait};
+use clap::Parser;
+
+// Struct for managing the necessary arguments for compacting a repository.
+//
+// Compacting doesn't require the repository key so no passphrase is needed.
+#[derive(Debug, Clone, Parser)]
+pub struct CompactArgs {
+    repository: String,
+    #[arg(short, long, value_parser = clap::value_parser!(u8).range(0..=99))]
+    threshold: Option<u8>,
+    #[command(flatten)]
+    remote: RemoteArgs,
+}
+
+impl BorgTrait for CompactArgs {
+    fn repository(&self) -> String {
+        self.repository.to_owned()
+    }
+
+    fn passphrase(&self) -> Option<String> {
+        None
+    }
+
+    fn remote(&self) -> RemoteArgs {
+        self.remote.to_owned()
+    }
+}
+
+impl CompactTrait for CompactArgs {
+    fn threshold(&self) -> Option<u8> {
+        self.threshold
+    }
+}
+
+// The space freed by compacting a repository, if borg reported it.
+#[derive(Debug, Clone, Default)]
+pub struct CompactResult {
+    pub freed_bytes: Option<u64>,
+}
+
+// Parses the size borg reports after compacting, for example
+// `compaction freed about 1.23 MB repository space.` into bytes.
+//
+// Borg formats sizes with decimal units so 1 kB is 1000 bytes.
+fn parse_freed_bytes(message: &str) -> Option<u64> {
+    let start = message.find("freed about ")? + "freed about ".len();
+    let end = message.find(" repository space")?;
+    let (number, unit) = message.get(start..end)?.trim().split_once(' ')?;
+
+    let multiplier = match unit {
+        "B" => 1.0,
+        "kB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        "PB" => 1e15,
+        _ => return None,
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .map(|n| (n * multiplier).round() as u64)
+}
+
+pub fn display_compact_info(compact_result: CompactResult) {
+    match compact_result.freed_bytes {
+        Some(n) => util::log_print(
+            &format!("Repository compacted, freed {} bytes", n),
+            util::LogLevel::Info,
+        ),
+        None => util::log_print("Repository compacted", util::LogLevel::Info),
+    }
+}
+
+// The entrypoint for the `compact` module where a variable implementing
+// CompactTrait is passed containing the repository to compact.
+//
+// Segments with less than `threshold` percent of freeable space are left
+// untouched, borg uses a threshold of 10% by default. The freed space is
+// parsed from borg's info output when available.
+pub fn compact_repository(compact_args: &impl CompactTrait) -> Result<CompactResult, CommandError> {
+    let mut args = vec!["--info".to_owned(), "compact".to_owned()];
+    if let Some(n) = compact_args.threshold() {
+        args.push(format!("--threshold={}", n));
+    }
+    args.push(compact_args.repository());
+
+    let command = borg_command(
+        args,
+        compact_args.passphrase(),
+        &compact_args.common_options(),
+    );
+    let output = execute_borg(command)?;
+    let freed_bytes = output
+        .messages
+        .iter()
+        .filter_map(|m| m.message.as_deref())
+        .find_map(parse_freed_bytes);
+
+    Ok(CompactResult { freed_bytes })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup_compact_args() -> CompactArgs {
+        CompactArgs {
+            repository: "repository".to_owned(),
+            threshold: Some(20),
+            remote: RemoteArgs::default(),
+        }
+    }
+
+    #[test]
+    fn test_get_repository() {
+        let compact_args = setup_compact_args();
+        assert_eq!(compact_args.repository(), "repository");
+    }
+
+    #[test]
+    fn test_get_passphrase() {
+        let compact_args = setup_compact_args();
+        assert_eq!(compact_args.passphrase(), None);
+    }
+
+    #[test]
+    fn test_get_threshold() {
+        let mut compact_args = setup_compact_args();
+        assert_eq!(compact_args.threshold(), Some(20));
+
+        compact_args.threshold = None;
+        assert_eq!(compact_args.threshold(), None);
+    }
+
+    #[test]
+    fn test_parse_freed_bytes() {
+        let result1 = parse_freed_bytes("compaction freed about 1.23 MB repository space.");
+        assert_eq!(result1, Some(1_230_000));
+
+        let result2 = parse_freed_bytes("compaction freed about 512 B repository space.");
+        assert_eq!(result2, Some(512));
+
+        assert_eq!(
+            parse_freed_bytes("compaction freed about 1 XB repository space."),
+            None
+        );
+        assert_eq!(parse_freed_bytes("Finished compaction"), None);
+    }
+
+    #[test]
+    fn test_compact_repository_fail() {
+        let mut compact_args = setup_compact_args();
+        compact_args.repository = util::get_random_string(10);
+        assert!(compact_repository(&compact_args).is_err());
+    }
+}
@@ -1,18 +1,35 @@
+use super::super::config::{ConfigError, Profile, ProfileTrait};
 use super::super::util;
-use super::errors::ArchiveError;
+use super::command::{borg_command, execute_borg};
+use super::errors::{ArchiveError, CommandError};
+use super::passphrase::{PassphraseArgs, PassphraseTrait};
+use super::remote::RemoteArgs;
 use super::{BorgTrait, CreateTrait};
-use borgbackup::common::{CommonOptions, CreateOptions, Pattern, PatternInstruction};
+use borgbackup::common::{CreateOptions, Pattern, PatternInstruction};
 use borgbackup::output::create::Create;
-use borgbackup::sync::create;
 use clap::Parser;
+use serde::Serialize;
 use std::time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH};
 
+// An archive created by `create` while some files couldn't be read, printed
+// with `--output json` along with borg's warnings about those files.
+#[derive(Debug, Clone, Serialize)]
+pub struct PartialBackup {
+    pub archive: Create,
+    pub warnings: Vec<String>,
+}
+
 // Struct for managing the necessary arguments for creating an archive.
 #[derive(Debug, Clone, Parser)]
 pub struct CreateArgs {
-    repository: String,
+    #[arg(required_unless_present = "profile")]
+    repository: Option<String>,
     #[arg(short, long)]
-    passphrase: String,
+    passphrase: Option<String>,
+    #[command(flatten)]
+    passphrase_source: PassphraseArgs,
+    #[command(flatten)]
+    remote: RemoteArgs,
     #[arg(short, long)]
     archive: Option<String>,
     #[arg(long, num_args = 1.., value_delimiter = ' ')]
@@ -21,16 +38,35 @@ pub struct CreateArgs {
     include_patterns: Option<Vec<String>>,
     #[arg(long, num_args = 1.., value_delimiter = ' ')]
     exclude_patterns: Option<Vec<String>>,
+    #[arg(long)]
+    profile: Option<String>,
 }
 
 impl BorgTrait for CreateArgs {
     fn repository(&self) -> String {
-        self.repository.to_owned()
+        self.repository.to_owned().unwrap_or_default()
     }
 
-    fn passphrase(&self) -> String {
+    fn passphrase(&self) -> Option<String> {
         self.passphrase.to_owned()
     }
+
+    fn remote(&self) -> RemoteArgs {
+        self.remote.to_owned()
+    }
+}
+
+impl PassphraseTrait for CreateArgs {
+    fn passphrase_source(&self) -> &PassphraseArgs {
+        &self.passphrase_source
+    }
+
+    fn with_passphrase(self, passphrase: String) -> Self {
+        CreateArgs {
+            passphrase: Some(passphrase),
+            ..self
+        }
+    }
 }
 
 impl CreateTrait for CreateArgs {
@@ -51,20 +87,88 @@ impl CreateTrait for CreateArgs {
     }
 }
 
+impl ProfileTrait for CreateArgs {
+    fn profile(&self) -> Option<String> {
+        self.profile.to_owned()
+    }
+
+    fn profile_options(&self) -> Vec<&'static str> {
+        let options = [
+            ("--repository", self.repository.is_some()),
+            ("--passphrase", self.passphrase.is_some()),
+            ("--archive", self.archive.is_some()),
+            ("--paths", !self.paths.is_empty()),
+            ("--include-patterns", self.include_patterns.is_some()),
+            ("--exclude-patterns", self.exclude_patterns.is_some()),
+        ];
+        options
+            .into_iter()
+            .filter(|(_, given)| *given)
+            .map(|(option, _)| option)
+            .collect()
+    }
+
+    fn apply_profile(self, name: &str, profile: &Profile) -> Result<Self, ConfigError> {
+        let repository = self
+            .repository
+            .or_else(|| profile.repository.to_owned())
+            .ok_or_else(|| ConfigError::MissingValue(name.to_owned(), "repository".to_owned()))?;
+        let paths = match self.paths.is_empty() {
+            true => profile.paths.to_owned(),
+            false => self.paths,
+        };
+
+        Ok(CreateArgs {
+            repository: Some(repository),
+            passphrase: match self.passphrase {
+                Some(n) => Some(n),
+                None => profile.passphrase(name)?,
+            },
+            archive: self.archive.or_else(|| profile.archive.to_owned()),
+            paths,
+            include_patterns: self
+                .include_patterns
+                .or_else(|| profile.include_patterns.to_owned()),
+            exclude_patterns: self
+                .exclude_patterns
+                .or_else(|| profile.exclude_patterns.to_owned()),
+            ..self
+        })
+    }
+}
+
 // Creates a CreateOption struct using the struct's `new`
 // then manually sets the passphrase after `new` is called.
 fn new_create_options(
     repository: String,
-    passphrase: String,
+    passphrase: Option<String>,
     paths: Vec<String>,
     archive: String,
     pattern_instructions: Vec<PatternInstruction>,
 ) -> CreateOptions {
     let mut create_options = CreateOptions::new(repository, archive, paths, pattern_instructions);
-    create_options.passphrase = Some(passphrase);
+    create_options.passphrase = passphrase;
     create_options
 }
 
+// Converts CreateOptions into the arguments of `borg create`, with the
+// statistics of the new archive written to the `stdout` as JSON.
+fn to_args(create_options: &CreateOptions) -> Vec<String> {
+    let mut args = vec!["create".to_owned(), "--json".to_owned()];
+    args.extend(
+        create_options
+            .patterns
+            .iter()
+            .map(|n| format!("--pattern={}", n)),
+    );
+    args.push(format!(
+        "{}::{}",
+        create_options.repository, create_options.archive
+    ));
+    args.extend(create_options.paths.to_owned());
+    args
+}
+
 // Prints the command used for the BorgBackup crate.
 fn print_used_command(commands: Vec<String>) {
     let mut command = String::new();
@@ -78,7 +182,7 @@ fn print_used_command(commands: Vec<String>) {
 // based upon a provided Option Vector of type String
 // If `include_patterns` and `exclude_patterns` are of type None then an
 // empty Vector of type String will be returned.
-fn generate_pattern_instructions(
+pub fn generate_pattern_instructions(
     include_patterns: Option<Vec<String>>,
     exclude_patterns: Option<Vec<String>>,
 ) -> Vec<PatternInstruction> {
@@ -136,7 +240,9 @@ pub fn display_create_info(create_result: Create) {
 // and pattern instructions are generated from include and excude Vectors.
 //
 // Upon a successful archive creation the start and end time, duration and
-// commands used are displayed.
+// commands used are displayed. When borg exits with a warning the archive
+// was still created, but some files couldn't be read, so it is returned
+// as a partial backup along with borg's warnings.
 pub fn create_archive(create_args: &impl CreateTrait) -> Result<Create, ArchiveError> {
     let archive_name: String = create_args.archive().unwrap_or(match get_epoch_name() {
         Ok(n) => n,
@@ -155,12 +261,26 @@ pub fn create_archive(create_args: &impl CreateTrait) -> Result<Create, ArchiveE
         archive_name,
         pattern_instructions,
     );
-    let common_options = CommonOptions::default();
-
-    match create(&create_options, &common_options) {
-        Ok(n) => return Ok(n),
-        Err(e) => return Err(ArchiveError::ArchiveCreateError(e)),
-    };
+    let command = borg_command(
+        to_args(&create_options),
+        create_options.passphrase.to_owned(),
+        &create_args.common_options(),
+    );
+    let output = execute_borg(command).map_err(ArchiveError::ArchiveCreateError)?;
+    let create_result: Create = serde_json::from_str(&output.stdout).map_err(|e| {
+        ArchiveError::ArchiveCreateError(CommandError::Unknown(format!(
+            "Invalid borg output: {}",
+            e
+        )))
+    })?;
+
+    match output.exit_code {
+        0 => Ok(create_result),
+        _ => Err(ArchiveError::PartialBackup(
+            Box::new(create_result),
+            output.warnings(),
+        )),
+    }
 }
 
 #[cfg(test)]
@@ -171,12 +291,15 @@ mod tests {
 
     fn setup_create_args() -> CreateArgs {
         CreateArgs {
-            repository: String::from("repository"),
-            passphrase: String::from("passphrase"),
+            repository: Some(String::from("repository")),
+            passphrase: Some(String::from("passphrase")),
             archive: Some(String::from("archive")),
             paths: Vec::new(),
             include_patterns: Some(Vec::new()),
             exclude_patterns: Some(Vec::new()),
+            profile: None,
+            passphrase_source: PassphraseArgs::default(),
+            remote: RemoteArgs::default(),
         }
     }
 
@@ -189,7 +312,7 @@ mod tests {
     #[test]
     fn test_get_passphrase() {
         let create_args = setup_create_args();
-        assert_eq!(create_args.passphrase(), "passphrase")
+        assert_eq!(create_args.passphrase(), Some("passphrase".to_owned()))
     }
 
     #[test]
@@ -248,7 +371,7 @@ mod tests {
     fn test_new_create_options() {
         let create_options = new_create_options(
             "repository".to_owned(),
-            "passphrase".to_owned(),
+            Some("passphrase".to_owned()),
             Vec::new(),
             "archive".to_owned(),
             Vec::new(),
@@ -259,6 +382,29 @@ mod tests {
         assert_eq!(create_options.archive, "archive");
         assert_eq!(create_options.patterns.len(), 0);
     }
+
+    #[test]
+    fn test_to_args() {
+        let create_options = new_create_options(
+            "repository".to_owned(),
+            None,
+            vec!["/home".to_owned(), "/etc".to_owned()],
+            "archive".to_owned(),
+            generate_pattern_instructions(None, Some(vec!["**/.cache".to_owned()])),
+        );
+        assert_eq!(
+            to_args(&create_options),
+            [
+                "create",
+                "--json",
+                "--pattern=- sh:**/.cache",
+                "repository::archive",
+                "/home",
+                "/etc"
+            ]
+        );
+    }
+
     #[test]
     fn test_generate_pattern_instructions_some() {
         let include: Option<Vec<String>> = Some(vec!["test_include".to_owned()]);
@@ -326,16 +472,13 @@ mod tests {
         let target_dir = util::get_temp_directory();
         let passphrase = "passphrase".to_owned();
 
-        let init_args = init::InitArgs {
-            repository: repo_dir.clone(),
-            passphrase: passphrase.clone(),
-        };
+        let init_args = init::InitArgs::new(&repo_dir, &passphrase);
         let _ = init::initialise_repository(&init_args);
 
         let mut create_args = setup_create_args();
-        create_args.repository = repo_dir.clone();
+        create_args.repository = Some(repo_dir.clone());
         create_args.paths = vec![target_dir.clone()];
-        create_args.passphrase = passphrase;
+        create_args.passphrase = Some(passphrase);
 
         match create_archive(&create_args) {
             Ok(n) => {
@@ -352,26 +495,104 @@ mod tests {
         let target_dir = util::get_random_string(10);
         let passphrase = "passphrase".to_owned();
 
-        let init_args = init::InitArgs {
-            repository: repo_dir.clone(),
-            passphrase: passphrase.clone(),
-        };
+        let init_args = init::InitArgs::new(&repo_dir, &passphrase);
         let _ = init::initialise_repository(&init_args);
 
         let mut create_args = setup_create_args();
-        create_args.repository = repo_dir.clone();
+        create_args.repository = Some(repo_dir.clone());
         create_args.paths = vec![target_dir.clone()];
-        create_args.passphrase = util::get_random_string(10);
+        create_args.passphrase = Some(util::get_random_string(10));
 
         match create_archive(&create_args) {
             Ok(_) => assert!(false),
             Err(e) => match e {
-                ArchiveError::ArchiveCreateError(create_error) => match create_error {
-                    borgbackup::errors::CreateError::PassphraseWrong => assert!(true),
+                ArchiveError::ArchiveCreateError(command_error) => match command_error {
+                    CommandError::PassphraseWrong => assert!(true),
                     _ => assert!(false),
                 },
-                ArchiveError::EpochTimeError => assert!(false),
+                _ => assert!(false),
             },
         }
     }
+
+    #[test]
+    fn test_apply_profile() {
+        let profile = Profile {
+            repository: Some("profile_repository".to_owned()),
+            passphrase: Some("profile_passphrase".to_owned()),
+            paths: vec!["/home".to_owned()],
+            exclude_patterns: Some(vec!["**/.cache".to_owned()]),
+            ..Default::default()
+        };
+        let mut create_args = setup_create_args();
+        create_args.repository = None;
+        create_args.exclude_patterns = None;
+
+        let result = create_args.apply_profile("nightly", &profile).unwrap();
+        assert_eq!(result.repository(), "profile_repository");
+        assert_eq!(result.passphrase(), Some("passphrase".to_owned()));
+        assert_eq!(result.archive(), Some("archive".to_owned()));
+        assert_eq!(result.paths(), vec!["/home"]);
+        assert_eq!(
+            result.exclude_patterns(),
+            Some(vec!["**/.cache".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_apply_profile_missing_repository_fail() {
+        let mut create_args = setup_create_args();
+        create_args.repository = None;
+
+        match create_args.apply_profile("nightly", &Profile::default()) {
+            Err(ConfigError::MissingValue(name, value)) => {
+                assert_eq!(name, "nightly");
+                assert_eq!(value, "repository");
+            }
+            _ => panic!("Expected a missing repository error"),
+        }
+    }
+
+    #[test]
+    fn test_partial_backup_json() {
+        let archive: Create = serde_json::from_str(
+            r#"{
+                "repository": {
+                    "id": "0000",
+                    "location": "/srv/borg",
+                    "last_modified": "2025-01-01T02:00:00.000000"
+                },
+                "cache": null,
+                "encryption": null,
+                "archive": {
+                    "id": "1111",
+                    "name": "archive",
+                    "command_line": ["borg", "create"],
+                    "limits": {"max_archive_size": 0.0},
+                    "duration": 1.0,
+                    "chunker_params": null,
+                    "start": "2025-01-01T02:00:00.000000",
+                    "end": "2025-01-01T02:00:01.000000",
+                    "stats": {
+                        "compressed_size": 0,
+                        "deduplicated_size": 0,
+                        "nfiles": 1,
+                        "original_size": 0
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let partial_backup = PartialBackup {
+            archive,
+            warnings: vec!["/home/user/locked: open: [Errno 13] Permission denied".to_owned()],
+        };
+
+        let result = serde_json::to_value(&partial_backup).unwrap();
+        assert_eq!(result["archive"]["archive"]["name"], "archive");
+        assert_eq!(
+            result["warnings"],
+            serde_json::json!(["/home/user/locked: open: [Errno 13] Permission denied"])
+        );
+    }
 }
@@ -1,19 +1,363 @@
-use super::super::util;
-use borgbackup::errors::CreateError;
+use borgbackup::errors::InitError;
+use borgbackup::output::create::Create;
+use std::fmt;
 
 #[derive(Debug)]
 pub enum ArchiveError {
     EpochTimeError,
-    ArchiveCreateError(CreateError),
+    ArchiveCreateError(CommandError),
+    PartialBackup(Box<Create>, Vec<String>),
 }
 
-pub fn parse_archive_error(archive_error: ArchiveError) {
-    match archive_error {
-        ArchiveError::EpochTimeError => {
-            util::log_print("Error retriving SystemTime since 1970!", util::LogLevel::Info)
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::EpochTimeError => write!(f, "Error retriving SystemTime since 1970!"),
+            ArchiveError::ArchiveCreateError(command_err) => write!(f, "{}", command_err),
+            ArchiveError::PartialBackup(create, warnings) => write!(
+                f,
+                "Archive {} was created with {} warning(s), some files may be missing",
+                create.archive.name,
+                warnings.len()
+            ),
         }
-        ArchiveError::ArchiveCreateError(create_err) => {
-            util::log_print(&format!("{:?}", create_err), util::LogLevel::Error)
+    }
+}
+
+impl ArchiveError {
+    // A short identifier for the error, recorded in the scheduler's run history.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ArchiveError::EpochTimeError => "epoch_time",
+            ArchiveError::ArchiveCreateError(command_err) => match command_err {
+                CommandError::ArchiveAlreadyExists => "archive_already_exists",
+                CommandError::PassphraseWrong => "passphrase_wrong",
+                CommandError::RepositoryDoesNotExist => "repository_does_not_exist",
+                CommandError::LockFailed => "lock_failed",
+                CommandError::ConnectionClosed => "connection_closed",
+                CommandError::CommandFailed(_) => "command_failed",
+                CommandError::TerminatedBySignal => "terminated_by_signal",
+                _ => "create_failed",
+            },
+            ArchiveError::PartialBackup(_, _) => "partial_backup",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    PassphraseRequired(String),
+    PassphraseNotRequired(String),
+    InvalidStorageQuota(String),
+    RepositoryInitError(InitError),
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepositoryError::PassphraseRequired(mode) => {
+                write!(f, "A passphrase is required for {} encryption", mode)
+            }
+            RepositoryError::PassphraseNotRequired(mode) => {
+                write!(f, "A passphrase cannot be used with {} encryption", mode)
+            }
+            RepositoryError::InvalidStorageQuota(quota) => {
+                write!(
+                    f,
+                    "Invalid storage quota '{}', expected a size such as 500G",
+                    quota
+                )
+            }
+            RepositoryError::RepositoryInitError(init_err) => write!(f, "{}", init_err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    CommandFailed(std::io::Error),
+    TerminatedBySignal,
+    PassphraseWrong,
+    RepositoryDoesNotExist,
+    ArchiveDoesNotExist,
+    ArchiveAlreadyExists,
+    LockFailed,
+    ConnectionClosed,
+    Unknown(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::CommandFailed(err) => write!(f, "Failed to execute borg: {}", err),
+            CommandError::TerminatedBySignal => write!(f, "Borg was terminated by a signal"),
+            CommandError::PassphraseWrong => write!(f, "The passphrase supplied is incorrect"),
+            CommandError::RepositoryDoesNotExist => write!(f, "Repository does not exist"),
+            CommandError::ArchiveDoesNotExist => write!(f, "Archive does not exist"),
+            CommandError::ArchiveAlreadyExists => write!(f, "Archive already exists"),
+            CommandError::LockFailed => {
+                write!(
+                    f,
+                    "Failed to acquire the repository lock, is borg already running?"
+                )
+            }
+            CommandError::ConnectionClosed => {
+                write!(f, "The connection to the repository was closed")
+            }
+            CommandError::Unknown(message) => write!(f, "Unknown error occurred: {}", message),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RetentionError {
+    NoRetentionRules,
+    PruneCommandError(CommandError),
+}
+
+impl fmt::Display for RetentionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetentionError::NoRetentionRules => {
+                write!(
+                    f,
+                    "At least one --keep option is required to prune archives"
+                )
+            }
+            RetentionError::PruneCommandError(command_err) => write!(f, "{}", command_err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CheckError {
+    RepairNotConfirmed,
+    CheckCommandError(CommandError),
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::RepairNotConfirmed => write!(
+                f,
+                "Repairing can lose data, pass --confirm-repair to repair the repository"
+            ),
+            CheckError::CheckCommandError(command_err) => write!(f, "{}", command_err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExtractError {
+    TargetDirectoryError(std::io::Error),
+    ExtractCommandError(CommandError),
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::TargetDirectoryError(io_err) => {
+                write!(f, "Failed to create the target directory: {}", io_err)
+            }
+            ExtractError::ExtractCommandError(command_err) => write!(f, "{}", command_err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PassphraseError {
+    EnvironmentVariableMissing(String),
+    FileReadError(String, std::io::Error),
+    InsecurePermissions(String, u32),
+    CommandFailed(String, String),
+    PromptError(std::io::Error),
+    EmptyPassphrase,
+}
+
+impl fmt::Display for PassphraseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassphraseError::EnvironmentVariableMissing(variable) => {
+                write!(f, "Environment variable {} is not set", variable)
+            }
+            PassphraseError::FileReadError(path, io_err) => {
+                write!(f, "Failed to read passphrase file {}: {}", path, io_err)
+            }
+            PassphraseError::InsecurePermissions(path, mode) => write!(
+                f,
+                "Passphrase file {} has permissions {:o}, it must not be accessible by group or others",
+                path, mode
+            ),
+            PassphraseError::CommandFailed(passcommand, reason) => {
+                write!(f, "Passcommand '{}' failed: {}", passcommand, reason)
+            }
+            PassphraseError::PromptError(io_err) => {
+                write!(f, "Failed to read passphrase: {}", io_err)
+            }
+            PassphraseError::EmptyPassphrase => write!(f, "The passphrase supplied is empty"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DaemonError {
+    AlreadyRunning(String, Option<String>),
+    PidFileError(String, std::io::Error),
+    LogFileError(String, std::io::Error),
+    DaemonizeError(daemonize::Error),
+}
+
+impl fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DaemonError::AlreadyRunning(path, Some(pid)) => write!(
+                f,
+                "Another scheduler is already running with PID {} ({})",
+                pid, path
+            ),
+            DaemonError::AlreadyRunning(path, None) => {
+                write!(f, "Another scheduler is already running ({})", path)
+            }
+            DaemonError::PidFileError(path, io_err) => {
+                write!(f, "Failed to open PID file {}: {}", path, io_err)
+            }
+            DaemonError::LogFileError(path, io_err) => {
+                write!(f, "Failed to open log file {}: {}", path, io_err)
+            }
+            DaemonError::DaemonizeError(daemonize_err) => {
+                write!(f, "Failed to daemonize: {}", daemonize_err)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemdError {
+    InvalidSchedule(ScheduleError),
+    UnsupportedExpression(String),
+    ExecutableNotFound(std::io::Error),
+    WriteError(String, std::io::Error),
+    UnsupportedOptions(Vec<String>),
+}
+
+impl fmt::Display for SystemdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SystemdError::InvalidSchedule(schedule_err) => write!(f, "{}", schedule_err),
+            SystemdError::UnsupportedExpression(expression) => write!(
+                f,
+                "Unable to translate CRON expression '{}' into a systemd calendar event",
+                expression
+            ),
+            SystemdError::ExecutableNotFound(io_err) => {
+                write!(f, "Unable to find the rusty_borg executable: {}", io_err)
+            }
+            SystemdError::WriteError(path, io_err) => {
+                write!(f, "Failed to write {}: {}", path, io_err)
+            }
+            SystemdError::UnsupportedOptions(options) => write!(
+                f,
+                "{} only apply to `schedule` and have no effect on systemd units",
+                options.join(", ")
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    InvalidExpression(String, String),
+    InvalidTimezone(String),
+    ScheduleDaemonError(DaemonError),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::InvalidExpression(expression, reason) => {
+                write!(f, "Invalid CRON expression '{}': {}", expression, reason)
+            }
+            ScheduleError::InvalidTimezone(timezone) => {
+                write!(
+                    f,
+                    "Invalid timezone '{}', expected a name such as Europe/London",
+                    timezone
+                )
+            }
+            ScheduleError::ScheduleDaemonError(daemon_err) => write!(f, "{}", daemon_err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RemoteError {
+    InvalidRepository(String, String),
+    ConnectionFailed(String, std::io::Error),
+    SshError(ssh2::Error),
+    AuthenticationFailed(String, Vec<String>),
+    BorgNotFound(String, String),
+    UnsupportedBorgVersion(String, String),
+    RepositoryNotWritable(String, String),
+    RemoteShellFailed(String, String),
+    KnownHostsError(String, String),
+    UnknownHostKey(String, String),
+    HostKeyMismatch(String, String),
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteError::InvalidRepository(repository, reason) => write!(
+                f,
+                "Invalid repository '{}', expected ssh://[USER@]HOST[:PORT]/PATH: {}",
+                repository, reason
+            ),
+            RemoteError::ConnectionFailed(address, io_err) => {
+                write!(f, "Failed to connect to {}: {}", address, io_err)
+            }
+            RemoteError::SshError(ssh_err) => write!(f, "SSH error: {}", ssh_err),
+            RemoteError::AuthenticationFailed(login, methods) => match methods.is_empty() {
+                true => write!(
+                    f,
+                    "Failed to authenticate as {}, no authentication methods are available",
+                    login
+                ),
+                false => write!(
+                    f,
+                    "Failed to authenticate as {}, tried {}",
+                    login,
+                    methods.join(", ")
+                ),
+            },
+            RemoteError::BorgNotFound(host, command) => {
+                write!(
+                    f,
+                    "Running '{}' on {} failed, is borg installed?",
+                    command, host
+                )
+            }
+            RemoteError::UnsupportedBorgVersion(host, version) => {
+                write!(f, "Unsupported borg version on {}: {}", host, version)
+            }
+            RemoteError::RepositoryNotWritable(host, path) => {
+                write!(f, "Repository path {} on {} is not writable", path, host)
+            }
+            RemoteError::RemoteShellFailed(rsh, reason) => {
+                write!(f, "Remote shell '{}' failed: {}", rsh, reason)
+            }
+            RemoteError::KnownHostsError(file, reason) => {
+                write!(f, "Failed to use known hosts file {}: {}", file, reason)
+            }
+            RemoteError::UnknownHostKey(host, fingerprint) => write!(
+                f,
+                "Host key for {} is not known, its fingerprint is {}",
+                host, fingerprint
+            ),
+            RemoteError::HostKeyMismatch(host, fingerprint) => write!(
+                f,
+                "Host key for {} has changed, its fingerprint is now {}. The host may be impersonated",
+                host, fingerprint
+            ),
         }
     }
 }
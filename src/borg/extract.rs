@@ -0,0 +1,341 @@
+use super::super::util;
+use super::command::{borg_command, execute_borg};
+use super::create::generate_pattern_instructions;
+use super::errors::ExtractError;
+use super::passphrase::{PassphraseArgs, PassphraseTrait};
+use super::remote::RemoteArgs;
+use super::BorgTrait;
+use borgbackup::common::{Pattern, PatternInstruction};
+use clap::Parser;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// The repository and archive name given as `repository::archive`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveLocation {
+    pub repository: String,
+    pub archive: String,
+}
+
+impl fmt::Display for ArchiveLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}", self.repository, self.archive)
+    }
+}
+
+// Parses `repository::archive`, splitting on the last `::` so the
+// repository itself may contain colons, e.g. `ssh://host:22/repo::archive`.
+fn parse_archive_location(location: &str) -> Result<ArchiveLocation, String> {
+    match location.rsplit_once("::") {
+        Some((repository, archive)) if !repository.is_empty() && !archive.is_empty() => {
+            Ok(ArchiveLocation {
+                repository: repository.to_owned(),
+                archive: archive.to_owned(),
+            })
+        }
+        _ => Err(format!(
+            "Invalid archive '{}', expected REPOSITORY::ARCHIVE",
+            location
+        )),
+    }
+}
+
+// Struct for managing the necessary arguments for extracting an archive.
+#[derive(Debug, Clone, Parser)]
+pub struct ExtractArgs {
+    #[arg(value_parser = parse_archive_location)]
+    location: ArchiveLocation,
+    #[arg(short, long)]
+    passphrase: Option<String>,
+    #[command(flatten)]
+    passphrase_source: PassphraseArgs,
+    #[command(flatten)]
+    remote: RemoteArgs,
+    #[arg(short, long, default_value = ".")]
+    target: String,
+    #[arg(long, num_args = 1.., value_delimiter = ' ')]
+    paths: Vec<String>,
+    #[arg(long, num_args = 1.., value_delimiter = ' ')]
+    include_patterns: Option<Vec<String>>,
+    #[arg(long, num_args = 1.., value_delimiter = ' ')]
+    exclude_patterns: Option<Vec<String>>,
+    #[arg(long)]
+    strip_components: Option<u32>,
+    #[arg(short, long, default_value_t = false)]
+    dry_run: bool,
+}
+
+impl BorgTrait for ExtractArgs {
+    fn repository(&self) -> String {
+        self.location.repository.to_owned()
+    }
+
+    fn passphrase(&self) -> Option<String> {
+        self.passphrase.to_owned()
+    }
+
+    fn remote(&self) -> RemoteArgs {
+        self.remote.to_owned()
+    }
+}
+
+impl PassphraseTrait for ExtractArgs {
+    fn passphrase_source(&self) -> &PassphraseArgs {
+        &self.passphrase_source
+    }
+
+    fn with_passphrase(self, passphrase: String) -> Self {
+        ExtractArgs {
+            passphrase: Some(passphrase),
+            ..self
+        }
+    }
+}
+
+impl ExtractArgs {
+    // The include and exclude patterns as pattern instructions. Borg uses
+    // the first pattern a path matches, so the exclude patterns come first
+    // to take effect inside included directories. When include patterns
+    // are given everything else is excluded, as borg would otherwise
+    // extract paths that match no pattern.
+    fn pattern_instructions(&self) -> Vec<PatternInstruction> {
+        let mut pattern_instructions =
+            generate_pattern_instructions(None, self.exclude_patterns.to_owned());
+        pattern_instructions.extend(generate_pattern_instructions(
+            self.include_patterns.to_owned(),
+            None,
+        ));
+        if self.include_patterns.is_some() {
+            pattern_instructions.push(PatternInstruction::Exclude(Pattern::Shell("**".to_owned())));
+        }
+        pattern_instructions
+    }
+
+    // Converts the extract options into borg `extract` arguments, with the
+    // include and exclude patterns passed as pattern instructions.
+    fn to_args(&self) -> Vec<String> {
+        let mut args = vec!["extract".to_owned(), "--list".to_owned()];
+        if self.dry_run {
+            args.push("--dry-run".to_owned());
+        }
+        if let Some(n) = self.strip_components {
+            args.push(format!("--strip-components={}", n));
+        }
+
+        for pattern in self.pattern_instructions() {
+            args.push(format!("--pattern={}", pattern));
+        }
+
+        args.push(self.location.to_string());
+        args.extend(self.paths.to_owned());
+        args
+    }
+}
+
+// Canonicalizes the repository when it is a local path, remote
+// repositories and paths that don't exist are left to borg.
+fn absolute_location(location: &ArchiveLocation) -> ArchiveLocation {
+    let repository = Path::new(&location.repository);
+    match repository.exists() {
+        true => ArchiveLocation {
+            repository: repository
+                .canonicalize()
+                .map(|n| n.display().to_string())
+                .unwrap_or(location.repository.to_owned()),
+            archive: location.archive.to_owned(),
+        },
+        false => location.to_owned(),
+    }
+}
+
+// The paths extracted (or that would be extracted in a dry run) from an archive.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractResult {
+    pub paths: Vec<String>,
+    pub target: String,
+    pub dry_run: bool,
+}
+
+pub fn display_extract_info(extract_result: ExtractResult) {
+    if extract_result.dry_run {
+        for path in extract_result.paths.iter() {
+            util::log_print(&format!("Would extract: {}", path), util::LogLevel::Info);
+        }
+        util::log_print(
+            &format!("Would extract {} path(s)", extract_result.paths.len()),
+            util::LogLevel::Info,
+        );
+    } else {
+        util::log_print(
+            &format!(
+                "Extracted {} path(s) to {}",
+                extract_result.paths.len(),
+                extract_result.target
+            ),
+            util::LogLevel::Info,
+        );
+    }
+}
+
+// This is the entrypoint of the **extract** module where a variable of type
+// ExtractArgs is passed containing the archive to restore and where to.
+//
+// Borg extracts into its working directory, so the target directory is
+// created if needed and used as the working directory, with a local
+// repository made absolute so it is still found from there. A dry run
+// leaves the file system untouched and only lists what would be extracted.
+pub fn extract_archive(extract_args: &ExtractArgs) -> Result<ExtractResult, ExtractError> {
+    let extract_args = &ExtractArgs {
+        location: absolute_location(&extract_args.location),
+        ..extract_args.to_owned()
+    };
+    let mut command = borg_command(
+        extract_args.to_args(),
+        extract_args.passphrase(),
+        &extract_args.common_options(),
+    );
+    if !extract_args.dry_run {
+        fs::create_dir_all(&extract_args.target).map_err(ExtractError::TargetDirectoryError)?;
+        command.current_dir(&extract_args.target);
+    }
+
+    let output = execute_borg(command).map_err(ExtractError::ExtractCommandError)?;
+    Ok(ExtractResult {
+        paths: output.messages_from("borg.output.list"),
+        target: extract_args.target.to_owned(),
+        dry_run: extract_args.dry_run,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup_extract_args() -> ExtractArgs {
+        ExtractArgs {
+            location: parse_archive_location("repository::archive").unwrap(),
+            passphrase: Some("passphrase".to_owned()),
+            passphrase_source: PassphraseArgs::default(),
+            remote: RemoteArgs::default(),
+            target: ".".to_owned(),
+            paths: Vec::new(),
+            include_patterns: None,
+            exclude_patterns: None,
+            strip_components: None,
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn test_get_repository() {
+        let extract_args = setup_extract_args();
+        assert_eq!(extract_args.repository(), "repository");
+    }
+
+    #[test]
+    fn test_get_passphrase() {
+        let extract_args = setup_extract_args();
+        assert_eq!(extract_args.passphrase(), Some("passphrase".to_owned()));
+    }
+
+    #[test]
+    fn test_parse_archive_location() {
+        let result = parse_archive_location("ssh://user@host:22/repo::archive").unwrap();
+        assert_eq!(result.repository, "ssh://user@host:22/repo");
+        assert_eq!(result.archive, "archive");
+        assert_eq!(result.to_string(), "ssh://user@host:22/repo::archive");
+
+        assert!(parse_archive_location("repository").is_err());
+        assert!(parse_archive_location("repository::").is_err());
+        assert!(parse_archive_location("::archive").is_err());
+    }
+
+    #[test]
+    fn test_to_args() {
+        let mut extract_args = setup_extract_args();
+        assert_eq!(
+            extract_args.to_args(),
+            vec!["extract", "--list", "repository::archive"]
+        );
+
+        extract_args.dry_run = true;
+        extract_args.strip_components = Some(2);
+        extract_args.paths = vec!["home/user".to_owned()];
+        extract_args.exclude_patterns = Some(vec!["*.tmp".to_owned()]);
+        assert_eq!(
+            extract_args.to_args(),
+            vec![
+                "extract",
+                "--list",
+                "--dry-run",
+                "--strip-components=2",
+                "--pattern=- sh:*.tmp",
+                "repository::archive",
+                "home/user"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pattern_instructions() {
+        let mut extract_args = setup_extract_args();
+        assert!(extract_args.pattern_instructions().is_empty());
+
+        extract_args.exclude_patterns = Some(vec!["*.tmp".to_owned()]);
+        let result1: Vec<String> = extract_args
+            .pattern_instructions()
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(result1, ["- sh:*.tmp"]);
+
+        extract_args.include_patterns = Some(vec!["home/user/**".to_owned()]);
+        let result2: Vec<String> = extract_args
+            .pattern_instructions()
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(result2, ["- sh:*.tmp", "+ sh:home/user/**", "- sh:**"]);
+
+        // A file inside an included directory is matched by the exclude first.
+        extract_args.exclude_patterns = Some(vec!["home/user/.cache/**".to_owned()]);
+        let result3: Vec<String> = extract_args
+            .pattern_instructions()
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(
+            result3,
+            ["- sh:home/user/.cache/**", "+ sh:home/user/**", "- sh:**"]
+        );
+    }
+
+    #[test]
+    fn test_absolute_location() {
+        let directory = tempfile::tempdir().unwrap();
+        let location = ArchiveLocation {
+            repository: directory.path().join(".").display().to_string(),
+            archive: "archive".to_owned(),
+        };
+        assert_eq!(
+            absolute_location(&location).repository,
+            directory
+                .path()
+                .canonicalize()
+                .unwrap()
+                .display()
+                .to_string()
+        );
+
+        let location = parse_archive_location("ssh://host/./repo::archive").unwrap();
+        assert_eq!(absolute_location(&location), location);
+    }
+
+    #[test]
+    fn test_extract_archive_fail() {
+        let mut extract_args = setup_extract_args();
+        extract_args.target = util::get_temp_directory();
+        assert!(extract_archive(&extract_args).is_err());
+    }
+}
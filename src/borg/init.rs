@@ -1,14 +1,94 @@
-use super::BorgTrait;
-use borgbackup::common::{CommonOptions, EncryptionMode, InitOptions};
-use borgbackup::errors::InitError;
+use super::errors::RepositoryError;
+use super::passphrase::{PassphraseArgs, PassphraseTrait};
+use super::remote::RemoteArgs;
+use super::{BorgTrait, InitTrait};
+use borgbackup::common::{EncryptionMode, InitOptions};
 use borgbackup::sync::init;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::fmt;
+
+// The encryption modes supported by BorgBackup, selectable with `--encryption`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EncryptionType {
+    None,
+    Authenticated,
+    AuthenticatedBlake2,
+    Repokey,
+    Keyfile,
+    RepokeyBlake2,
+    #[default]
+    KeyfileBlake2,
+}
+
+impl EncryptionType {
+    // Every mode besides `none` protects the repository with a key,
+    // so a passphrase is required for it.
+    pub fn requires_passphrase(self) -> bool {
+        !matches!(self, EncryptionType::None)
+    }
+
+    // Builds the BorgBackup EncryptionMode for the selected type, validating
+    // that a passphrase is only provided when the mode needs one.
+    pub fn to_encryption_mode(
+        self,
+        passphrase: Option<String>,
+    ) -> Result<EncryptionMode, RepositoryError> {
+        let passphrase = match (self.requires_passphrase(), passphrase) {
+            (true, Some(n)) => n,
+            (true, None) => return Err(RepositoryError::PassphraseRequired(self.to_string())),
+            (false, Some(_)) => {
+                return Err(RepositoryError::PassphraseNotRequired(self.to_string()))
+            }
+            (false, None) => return Ok(EncryptionMode::None),
+        };
+
+        Ok(match self {
+            EncryptionType::None => EncryptionMode::None,
+            EncryptionType::Authenticated => EncryptionMode::Authenticated(passphrase),
+            EncryptionType::AuthenticatedBlake2 => EncryptionMode::AuthenticatedBlake2(passphrase),
+            EncryptionType::Repokey => EncryptionMode::Repokey(passphrase),
+            EncryptionType::Keyfile => EncryptionMode::Keyfile(passphrase),
+            EncryptionType::RepokeyBlake2 => EncryptionMode::RepokeyBlake2(passphrase),
+            EncryptionType::KeyfileBlake2 => EncryptionMode::KeyfileBlake2(passphrase),
+        })
+    }
+}
+
+impl fmt::Display for EncryptionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => write!(f, "{}", value.get_name()),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+// The repository created by `init` and the encryption mode it uses.
+#[derive(Debug, Clone, Serialize)]
+pub struct InitResult {
+    pub repository: String,
+    pub encryption: EncryptionType,
+}
 
 // Struct for managing the necessary arguments for initialising a repository.
-#[derive(Debug, Clone, Parser)]
+#[derive(Debug, Clone, Parser, Default)]
 pub struct InitArgs {
     pub repository: String,
-    pub passphrase: String,
+    pub passphrase: Option<String>,
+    #[command(flatten)]
+    pub passphrase_source: PassphraseArgs,
+    #[command(flatten)]
+    pub remote: RemoteArgs,
+    #[arg(short, long, value_enum, default_value_t = EncryptionType::KeyfileBlake2)]
+    pub encryption: EncryptionType,
+    #[arg(long, default_value_t = false)]
+    pub append_only: bool,
+    #[arg(long)]
+    pub storage_quota: Option<String>,
+    #[arg(long, default_value_t = false)]
+    pub make_parent_dirs: bool,
 }
 
 impl BorgTrait for InitArgs {
@@ -16,43 +96,131 @@ impl BorgTrait for InitArgs {
         self.repository.to_owned()
     }
 
-    fn passphrase(&self) -> String {
+    fn passphrase(&self) -> Option<String> {
         self.passphrase.to_owned()
     }
+
+    fn remote(&self) -> RemoteArgs {
+        self.remote.to_owned()
+    }
+}
+
+impl PassphraseTrait for InitArgs {
+    fn passphrase_source(&self) -> &PassphraseArgs {
+        &self.passphrase_source
+    }
+
+    fn with_passphrase(self, passphrase: String) -> Self {
+        InitArgs {
+            passphrase: Some(passphrase),
+            ..self
+        }
+    }
 }
 
-// The entrypoint for the `init` module where a variable of type
-// InitArgs is passed containing the necessary information
+impl InitTrait for InitArgs {
+    fn encryption(&self) -> EncryptionType {
+        self.encryption
+    }
+
+    fn append_only(&self) -> bool {
+        self.append_only
+    }
+
+    fn storage_quota(&self) -> Option<String> {
+        self.storage_quota.to_owned()
+    }
+
+    fn make_parent_dirs(&self) -> bool {
+        self.make_parent_dirs
+    }
+}
+
+impl InitArgs {
+    #[allow(dead_code)]
+    pub fn new(repository: &str, passphrase: &str) -> InitArgs {
+        InitArgs {
+            repository: repository.to_owned(),
+            passphrase: Some(passphrase.to_owned()),
+            ..Default::default()
+        }
+    }
+}
+
+// Parses a human-readable storage quota such as `500G` or `1.5T` into the
+// form BorgBackup expects, where the optional unit is one of K, M, G, T or P.
+//
+// An error is returned if the quota is not a positive number with a valid unit.
+fn parse_storage_quota(storage_quota: &str) -> Result<String, RepositoryError> {
+    let invalid = || RepositoryError::InvalidStorageQuota(storage_quota.to_owned());
+
+    let quota = storage_quota.trim().to_uppercase();
+    let quota = quota.strip_suffix('B').unwrap_or(&quota);
+    let (number, unit) = match quota.char_indices().last() {
+        Some((i, c)) if "KMGTP".contains(c) => (&quota[..i], &quota[i..]),
+        Some(_) => (quota, ""),
+        None => return Err(invalid()),
+    };
+
+    if number.starts_with('.') || number.ends_with('.') {
+        return Err(invalid());
+    }
+    match number.parse::<f64>() {
+        Ok(n) if n > 0.0 && number.chars().all(|c| c.is_ascii_digit() || c == '.') => {
+            Ok(format!("{}{}", number, unit))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// The entrypoint for the `init` module where a variable implementing
+// InitTrait is passed containing the necessary information
 // to create a borg repository.
 //
 // An InitOptions struct is created from consuming InitArgs parameter
-// with default CommonOptions used for default behaviour.
+// with default CommonOptions used for default behaviour. The passphrase
+// is validated against the selected encryption mode and the storage quota
+// is parsed before calling borg.
 //
-// The function will print to the `stdout` upon creating a repository,
-// else it will printing to the `stderr` if the operation failed.
-pub fn initialise_repository(init_args: &impl BorgTrait) -> Result<(), InitError> {
+// The encryption mode used is returned upon creating a repository,
+// else the error is propagated to the caller.
+pub fn initialise_repository(
+    init_args: &impl InitTrait,
+) -> Result<EncryptionType, RepositoryError> {
+    let encryption = init_args.encryption();
+    let storage_quota = match init_args.storage_quota() {
+        Some(n) => Some(parse_storage_quota(&n)?),
+        None => None,
+    };
     let init_options = InitOptions {
         repository: init_args.repository(),
-        encryption_mode: EncryptionMode::KeyfileBlake2(init_args.passphrase()),
-        append_only: false,
-        make_parent_dirs: false,
-        storage_quota: None,
+        encryption_mode: encryption.to_encryption_mode(init_args.passphrase())?,
+        append_only: init_args.append_only(),
+        make_parent_dirs: init_args.make_parent_dirs(),
+        storage_quota,
     };
-    let common_options = CommonOptions::default();
+    let common_options = init_args.common_options();
 
-    init(&init_options, &common_options)?;
-    Ok(())
+    init(&init_options, &common_options).map_err(RepositoryError::RepositoryInitError)?;
+    Ok(encryption)
 }
 
 #[cfg(test)]
 mod test {
     use super::super::super::util;
     use super::*;
+    use borgbackup::errors::InitError;
 
     fn setup_init_args() -> InitArgs {
         InitArgs {
             repository: String::from("repository"),
-            passphrase: String::from("passphrase"),
+            passphrase: Some(String::from("passphrase")),
+            passphrase_source: PassphraseArgs::default(),
+            remote: RemoteArgs::default(),
+            encryption: EncryptionType::KeyfileBlake2,
+            append_only: false,
+            storage_quota: None,
+            make_parent_dirs: false,
         }
     }
     #[test]
@@ -64,7 +232,7 @@ mod test {
     #[test]
     fn test_get_passphrase() {
         let init_args = setup_init_args();
-        assert_eq!(init_args.passphrase, "passphrase");
+        assert_eq!(init_args.passphrase(), Some("passphrase".to_owned()));
     }
 
     #[test]
@@ -88,7 +256,9 @@ mod test {
         match initialise_repository(&init_args) {
             Ok(_) => assert!(false),
             Err(e) => match e {
-                InitError::DeserializeError(_) => assert!(true),
+                RepositoryError::RepositoryInitError(InitError::DeserializeError(_)) => {
+                    assert!(true)
+                }
                 _ => assert!(false),
             },
         }
@@ -108,9 +278,100 @@ mod test {
         match initialise_repository(&init_args2) {
             Ok(_) => assert!(false),
             Err(e) => match e {
-                InitError::RepositoryAlreadyExists => assert!(true),
+                RepositoryError::RepositoryInitError(InitError::RepositoryAlreadyExists) => {
+                    assert!(true)
+                }
                 _ => assert!(false),
+            },
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        let result = InitArgs::new("repository", "passphrase");
+        assert_eq!(result.repository, "repository");
+        assert_eq!(result.passphrase, Some("passphrase".to_owned()));
+        assert_eq!(result.encryption, EncryptionType::KeyfileBlake2);
+    }
+
+    #[test]
+    fn test_encryption_type_display() {
+        assert_eq!(EncryptionType::None.to_string(), "none");
+        assert_eq!(
+            EncryptionType::AuthenticatedBlake2.to_string(),
+            "authenticated-blake2"
+        );
+        assert_eq!(EncryptionType::KeyfileBlake2.to_string(), "keyfile-blake2");
+    }
+
+    #[test]
+    fn test_to_encryption_mode_pass() {
+        let passphrase = Some("passphrase".to_owned());
+        for encryption in EncryptionType::value_variants() {
+            if !encryption.requires_passphrase() {
+                continue;
             }
+            let mode = encryption.to_encryption_mode(passphrase.clone()).unwrap();
+            assert_eq!(mode.to_string(), encryption.to_string());
         }
+
+        let mode = EncryptionType::None.to_encryption_mode(None).unwrap();
+        assert!(matches!(mode, EncryptionMode::None));
+    }
+
+    #[test]
+    fn test_to_encryption_mode_fail() {
+        let result1 = EncryptionType::Repokey.to_encryption_mode(None);
+        assert!(matches!(
+            result1,
+            Err(RepositoryError::PassphraseRequired(_))
+        ));
+
+        let result2 = EncryptionType::None.to_encryption_mode(Some("passphrase".to_owned()));
+        assert!(matches!(
+            result2,
+            Err(RepositoryError::PassphraseNotRequired(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_storage_quota_pass() {
+        assert_eq!(parse_storage_quota("500G").unwrap(), "500G");
+        assert_eq!(parse_storage_quota("1.5t").unwrap(), "1.5T");
+        assert_eq!(parse_storage_quota("10MB").unwrap(), "10M");
+        assert_eq!(parse_storage_quota("1048576").unwrap(), "1048576");
+    }
+
+    #[test]
+    fn test_parse_storage_quota_fail() {
+        for quota in ["", "G", "0G", "-5G", "5X", "1.G", "five", "5 G"] {
+            match parse_storage_quota(quota) {
+                Err(RepositoryError::InvalidStorageQuota(n)) => assert_eq!(n, quota),
+                _ => panic!("Expected {} to be an invalid storage quota", quota),
+            }
+        }
+    }
+
+    #[test]
+    fn test_initialise_repository_storage_quota_fail() {
+        let mut init_args = setup_init_args();
+        init_args.storage_quota = Some("lots".to_owned());
+
+        match initialise_repository(&init_args) {
+            Err(RepositoryError::InvalidStorageQuota(n)) => assert_eq!(n, "lots"),
+            _ => panic!("Expected an invalid storage quota error"),
+        }
+    }
+
+    #[test]
+    fn test_init_result_serialize() {
+        let init_result = InitResult {
+            repository: "repository".to_owned(),
+            encryption: EncryptionType::RepokeyBlake2,
+        };
+        assert_eq!(
+            serde_json::to_string(&init_result).unwrap(),
+            r#"{"repository":"repository","encryption":"repokey-blake2"}"#
+        );
     }
 }
@@ -1,72 +1,127 @@
 use super::super::util;
+use super::command::{borg_command, execute_borg};
+use super::errors::CommandError;
+use super::passphrase::{PassphraseArgs, PassphraseTrait};
+use super::remote::RemoteArgs;
 use super::BorgTrait;
-use borgbackup::common::{CommonOptions, ListOptions};
+use borgbackup::common::{ListOptions, Pattern, PatternInstruction};
 use borgbackup::errors::ListError;
+use borgbackup::output::list::ListRepository;
 use borgbackup::sync::list;
 use clap::Parser;
+use serde::{Deserialize, Serialize};
 
 // Struct for managing the necessary arguments for listing a
 // repository's details.
 #[derive(Debug, Clone, Parser, Default)]
 pub struct ListArgs {
     repository: String,
-    passphrase: String,
+    passphrase: Option<String>,
+    #[command(flatten)]
+    passphrase_source: PassphraseArgs,
+    #[command(flatten)]
+    remote: RemoteArgs,
     #[arg(short, long, default_value_t = false)]
     last_modified: bool,
     #[arg(short, long, default_value_t = false)]
     encryption: bool,
     #[arg(short, long, default_value_t = false)]
     archives: bool,
+    #[arg(short = 'n', long)]
+    archive_name: Option<String>,
+    #[arg(short, long, requires = "archive_name")]
+    glob: Option<String>,
+    #[arg(short, long, requires = "archive_name")]
+    depth: Option<usize>,
     // TODO: Add list options
 }
 
+// A single file system object stored in an archive, as output by
+// `borg list --json-lines`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ArchiveEntry {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub size: u64,
+    pub mtime: String,
+    pub mode: String,
+}
+
 impl BorgTrait for ListArgs {
     fn repository(&self) -> String {
         self.repository.to_owned()
     }
 
-    fn passphrase(&self) -> String {
+    fn passphrase(&self) -> Option<String> {
         self.passphrase.to_owned()
     }
+
+    fn remote(&self) -> RemoteArgs {
+        self.remote.to_owned()
+    }
+}
+
+impl PassphraseTrait for ListArgs {
+    fn passphrase_source(&self) -> &PassphraseArgs {
+        &self.passphrase_source
+    }
+
+    fn with_passphrase(self, passphrase: String) -> Self {
+        ListArgs {
+            passphrase: Some(passphrase),
+            ..self
+        }
+    }
 }
 
 impl ListArgs {
-    fn new(repository: &str, passphrase: &str) -> ListArgs {
+    pub fn archive_name(&self) -> Option<String> {
+        self.archive_name.to_owned()
+    }
+
+    fn new(repository: &str, passphrase: Option<String>) -> ListArgs {
         let mut list_args = ListArgs::default();
         list_args.repository = repository.to_owned();
-        list_args.passphrase = passphrase.to_owned();
+        list_args.passphrase = passphrase;
         list_args
     }
 }
 
-pub fn verify_repo_location(repository: &str, passphrase: &str) -> bool {
-    let list_args = ListArgs::new(repository, passphrase);
-    match list_contents(list_args) {
-        Ok(()) => true,
+// Returns true if the repository exists and can be opened, reaching
+// remote repositories with the same settings as the caller.
+pub fn verify_repo_location(args: &impl BorgTrait) -> bool {
+    let mut list_args = ListArgs::new(&args.repository(), args.passphrase());
+    list_args.remote = args.remote();
+    match list_contents(&list_args) {
+        Ok(_) => true,
         Err(_) => false,
     }
 }
 
 // The entrypoint for the `list` module where a variable of type
-// ListArgs is consumed containing the necessary information
+// ListArgs is passed containing the necessary information
 // to list a borg repository's details.
 //
 // A ListOptions struct is created from the ListArgs parameter
 // with default CommonOptions used for default behaviour.
 //
 // If a repository is not found then the function propagates the error.
-// Else the function will display the last modified time,
-// encryption used (if any) and the repository's
-// archives (if any).
-pub fn list_contents(list_args: ListArgs) -> Result<(), ListError> {
+// Else the repository's details are returned to be displayed.
+pub fn list_contents(list_args: &ListArgs) -> Result<ListRepository, ListError> {
     let list_options = ListOptions {
-        repository: list_args.repository,
-        passphrase: Some(list_args.passphrase),
+        repository: list_args.repository(),
+        passphrase: list_args.passphrase(),
     };
-    let common_options = CommonOptions::default();
+    let common_options = list_args.common_options();
 
-    let repository_details = list(&list_options, &common_options)?;
+    list(&list_options, &common_options)
+}
 
+// Displays the last modified time, encryption used (if any) and
+// the repository's archives (if any) as selected in ListArgs.
+pub fn display_list_info(list_args: &ListArgs, repository_details: ListRepository) {
     if list_args.last_modified {
         util::log_print(
             &format!(
@@ -103,7 +158,7 @@ pub fn list_contents(list_args: ListArgs) -> Result<(), ListError> {
         util::log_print("\nArchives:", util::LogLevel::Info);
         if repository_details.archives.len() == 0 {
             util::log_print("Repository has no archives", util::LogLevel::Warn);
-            return Ok(());
+            return;
         }
         repository_details.archives.iter().for_each(|archive| {
             util::log_print(
@@ -115,7 +170,72 @@ pub fn list_contents(list_args: ListArgs) -> Result<(), ListError> {
             );
         });
     }
-    Ok(())
+}
+
+// Returns true if the path is at most `depth` components deep,
+// so a depth of 1 only includes the top-level entries.
+fn within_depth(path: &str, depth: Option<usize>) -> bool {
+    match depth {
+        Some(n) => path.split('/').filter(|c| !c.is_empty()).count() <= n,
+        None => true,
+    }
+}
+
+// Parses the `--json-lines` output of `borg list`, one entry per line,
+// dropping any entries deeper than the depth limit.
+fn parse_archive_entries(
+    output: &str,
+    depth: Option<usize>,
+) -> Result<Vec<ArchiveEntry>, CommandError> {
+    let mut entries = Vec::new();
+    for line in output.lines().filter(|l| !l.trim().is_empty()) {
+        let entry: ArchiveEntry = serde_json::from_str(line)
+            .map_err(|e| CommandError::Unknown(format!("Invalid borg output: {}", e)))?;
+        if within_depth(&entry.path, depth) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+pub fn display_archive_entries(entries: Vec<ArchiveEntry>) {
+    if entries.is_empty() {
+        util::log_print("Archive has no matching entries", util::LogLevel::Warn);
+        return;
+    }
+    entries.iter().for_each(|entry| {
+        util::log_print(
+            &format!(
+                "{} {} {:>12} {} {}",
+                entry.kind, entry.mode, entry.size, entry.mtime, entry.path
+            ),
+            util::LogLevel::Info,
+        );
+    });
+}
+
+// Lists the contents of the archive named in ListArgs.
+//
+// When a glob is given only entries matching it are listed, by
+// including the glob and excluding everything else. The depth limit
+// is applied to the entries returned by borg.
+pub fn list_archive_contents(list_args: &ListArgs) -> Result<Vec<ArchiveEntry>, CommandError> {
+    let archive_name = list_args.archive_name().unwrap_or_default();
+    let mut args = vec!["list".to_owned(), "--json-lines".to_owned()];
+    if let Some(glob) = &list_args.glob {
+        let pattern_instructions = [
+            PatternInstruction::Include(Pattern::Shell(glob.to_owned())),
+            PatternInstruction::Exclude(Pattern::Shell("**".to_owned())),
+        ];
+        for pattern in pattern_instructions {
+            args.push(format!("--pattern={}", pattern));
+        }
+    }
+    args.push(format!("{}::{}", list_args.repository, archive_name));
+
+    let command = borg_command(args, list_args.passphrase(), &list_args.common_options());
+    let output = execute_borg(command)?;
+    parse_archive_entries(&output.stdout, list_args.depth)
 }
 
 #[cfg(test)]
@@ -127,10 +247,15 @@ mod test {
     fn setup_list_args() -> ListArgs {
         ListArgs {
             repository: "repository".to_owned(),
-            passphrase: "passphrase".to_owned(),
+            passphrase: Some("passphrase".to_owned()),
+            passphrase_source: PassphraseArgs::default(),
+            remote: RemoteArgs::default(),
             last_modified: false,
             encryption: false,
             archives: false,
+            archive_name: None,
+            glob: None,
+            depth: None,
         }
     }
 
@@ -143,13 +268,13 @@ mod test {
     #[test]
     fn test_get_passphrase() {
         let list_args = setup_list_args();
-        assert_eq!(list_args.passphrase(), "passphrase");
+        assert_eq!(list_args.passphrase(), Some("passphrase".to_owned()));
     }
 
     #[test]
     fn test_new() {
         let expected = setup_list_args();
-        let result = ListArgs::new("repository", "passphrase");
+        let result = ListArgs::new("repository", Some("passphrase".to_owned()));
         assert_eq!(result.repository, expected.repository);
         assert_eq!(result.passphrase, expected.passphrase);
         assert_eq!(result.last_modified, expected.last_modified);
@@ -161,35 +286,30 @@ mod test {
     fn test_verify_repo_location_pass() {
         let passphrase = "passphrase";
         let repo_dir = util::get_temp_directory();
-        let init_args = init::InitArgs {
-            repository: repo_dir.clone(),
-            passphrase: passphrase.to_owned(),
-        };
+        let init_args = init::InitArgs::new(&repo_dir, passphrase);
 
         let _ = init::initialise_repository(&init_args);
-        assert!(verify_repo_location(&repo_dir, passphrase));
+        assert!(verify_repo_location(&init_args));
     }
 
     #[test]
     fn test_verify_repo_location_fail() {
-        assert_eq!(verify_repo_location("test", "test"), false);
+        let list_args = ListArgs::new("test", Some("test".to_owned()));
+        assert_eq!(verify_repo_location(&list_args), false);
     }
 
     #[test]
     fn test_list_contents_pass() {
         let passphrase = "passphrase";
         let repo_dir = util::get_temp_directory();
-        let init_args = init::InitArgs {
-            repository: repo_dir.clone(),
-            passphrase: passphrase.to_owned(),
-        };
+        let init_args = init::InitArgs::new(&repo_dir, passphrase);
 
         let _ = init::initialise_repository(&init_args);
         let mut list_args = setup_list_args();
-        list_args.passphrase = passphrase.to_owned();
+        list_args.passphrase = Some(passphrase.to_owned());
         list_args.repository = repo_dir.clone();
 
-        match list_contents(list_args) {
+        match list_contents(&list_args) {
             Ok(_) => assert!(true),
             Err(_) => assert!(false),
         }
@@ -199,7 +319,7 @@ mod test {
     fn test_list_contents_fail() {
         let list_args1 = setup_list_args();
 
-        match list_contents(list_args1) {
+        match list_contents(&list_args1) {
             Ok(_) => assert!(false),
             Err(e) => match e {
                 ListError::RepositoryDoesNotExist => assert!(true),
@@ -208,17 +328,14 @@ mod test {
         }
         let passphrase = "passphrase";
         let repo_dir = util::get_temp_directory();
-        let init_args = init::InitArgs {
-            repository: repo_dir.clone(),
-            passphrase: passphrase.to_owned(),
-        };
+        let init_args = init::InitArgs::new(&repo_dir, passphrase);
 
         let _ = init::initialise_repository(&init_args);
         let mut list_args2 = setup_list_args();
-        list_args2.passphrase = "test".to_owned();
+        list_args2.passphrase = Some("test".to_owned());
         list_args2.repository = repo_dir;
 
-        match list_contents(list_args2) {
+        match list_contents(&list_args2) {
             Ok(_) => assert!(false),
             Err(e) => match e {
                 ListError::PassphraseWrong => assert!(true),
@@ -226,4 +343,50 @@ mod test {
             },
         }
     }
+
+    #[test]
+    fn test_get_archive_name() {
+        let mut list_args = setup_list_args();
+        assert_eq!(list_args.archive_name(), None);
+
+        list_args.archive_name = Some("archive".to_owned());
+        assert_eq!(list_args.archive_name(), Some("archive".to_owned()));
+    }
+
+    #[test]
+    fn test_within_depth() {
+        assert!(within_depth("home/user/file", None));
+        assert!(within_depth("home", Some(1)));
+        assert!(!within_depth("home/user", Some(1)));
+        assert!(within_depth("home/user/", Some(2)));
+    }
+
+    #[test]
+    fn test_parse_archive_entries() {
+        let output = concat!(
+            r#"{"type": "d", "mode": "drwxr-xr-x", "path": "home", "mtime": "2025-01-01T00:00:00.000000", "size": 0}"#,
+            "\n",
+            r#"{"type": "-", "mode": "-rw-r--r--", "path": "home/file", "mtime": "2025-01-01T00:00:00.000000", "size": 42}"#,
+            "\n"
+        );
+
+        let result1 = parse_archive_entries(output, None).unwrap();
+        assert_eq!(result1.len(), 2);
+        assert_eq!(result1[1].path, "home/file");
+        assert_eq!(result1[1].kind, "-");
+        assert_eq!(result1[1].size, 42);
+
+        let result2 = parse_archive_entries(output, Some(1)).unwrap();
+        assert_eq!(result2.len(), 1);
+        assert_eq!(result2[0].path, "home");
+
+        assert!(parse_archive_entries("not json", None).is_err());
+    }
+
+    #[test]
+    fn test_list_archive_contents_fail() {
+        let mut list_args = setup_list_args();
+        list_args.archive_name = Some("archive".to_owned());
+        assert!(list_archive_contents(&list_args).is_err());
+    }
 }
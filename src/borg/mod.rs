@@ -1,12 +1,34 @@
+use borgbackup::common::CommonOptions;
+
+pub mod check;
+pub mod command;
+pub mod compact;
 pub mod create;
 pub mod errors;
+pub mod extract;
 pub mod init;
 pub mod list;
+pub mod passphrase;
+pub mod prune;
+pub mod remote;
 pub mod schedule;
+pub mod systemd;
 
 pub trait BorgTrait {
     fn repository(&self) -> String;
-    fn passphrase(&self) -> String;
+    fn passphrase(&self) -> Option<String>;
+    fn remote(&self) -> remote::RemoteArgs;
+
+    fn common_options(&self) -> CommonOptions {
+        self.remote().common_options()
+    }
+}
+
+pub trait InitTrait: BorgTrait {
+    fn encryption(&self) -> init::EncryptionType;
+    fn append_only(&self) -> bool;
+    fn storage_quota(&self) -> Option<String>;
+    fn make_parent_dirs(&self) -> bool;
 }
 
 pub trait CreateTrait: BorgTrait {
@@ -15,3 +37,12 @@ pub trait CreateTrait: BorgTrait {
     fn include_patterns(&self) -> Option<Vec<String>>;
     fn exclude_patterns(&self) -> Option<Vec<String>>;
 }
+
+pub trait PruneTrait: BorgTrait {
+    fn retention(&self) -> prune::RetentionArgs;
+    fn dry_run(&self) -> bool;
+}
+
+pub trait CompactTrait: BorgTrait {
+    fn threshold(&self) -> Option<u8>;
+}
@@ -0,0 +1,281 @@
+use super::errors::PassphraseError;
+use clap::Args;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Stdio};
+
+// Struct for managing the alternative sources of a repository passphrase,
+// so it doesn't have to be given on the command line where it is visible
+// in the shell history and `ps` output. At most one source can be used,
+// and none of them can be combined with a passphrase argument.
+#[derive(Debug, Clone, Args, Default)]
+#[group(id = "passphrase_source", multiple = false)]
+pub struct PassphraseArgs {
+    #[arg(long, value_name = "VARIABLE", conflicts_with = "passphrase")]
+    pub passphrase_env: Option<String>,
+    #[arg(long, value_name = "FILE", conflicts_with = "passphrase")]
+    pub passphrase_file: Option<String>,
+    #[arg(long, value_name = "COMMAND", conflicts_with = "passphrase")]
+    pub passcommand: Option<String>,
+    #[arg(long, default_value_t = false, conflicts_with = "passphrase")]
+    pub passphrase_prompt: bool,
+}
+
+// Implemented by arguments that accept a passphrase source, allowing the
+// passphrase to be read once before the command is run.
+pub trait PassphraseTrait: Sized {
+    fn passphrase_source(&self) -> &PassphraseArgs;
+    fn with_passphrase(self, passphrase: String) -> Self;
+}
+
+impl PassphraseArgs {
+    // Reads the passphrase from the selected source, returning None if
+    // no source was selected.
+    pub fn read(&self) -> Result<Option<String>, PassphraseError> {
+        read_source(
+            self.passphrase_env.as_deref(),
+            self.passphrase_file.as_deref(),
+            self.passcommand.as_deref(),
+            self.passphrase_prompt.then_some("Enter passphrase: "),
+        )
+    }
+}
+
+// Struct for managing the sources of the passphrase of an encrypted SSH
+// key, which are read the same way as those of a repository passphrase.
+#[derive(Debug, Clone, Args, Default)]
+#[group(id = "key_passphrase_source", multiple = false)]
+pub struct KeyPassphraseArgs {
+    #[arg(long, value_name = "VARIABLE")]
+    pub key_passphrase_env: Option<String>,
+    #[arg(long, value_name = "FILE")]
+    pub key_passphrase_file: Option<String>,
+    #[arg(long, value_name = "COMMAND")]
+    pub key_passcommand: Option<String>,
+    #[arg(long, default_value_t = false)]
+    pub key_passphrase_prompt: bool,
+}
+
+impl KeyPassphraseArgs {
+    pub fn read(&self) -> Result<Option<String>, PassphraseError> {
+        read_source(
+            self.key_passphrase_env.as_deref(),
+            self.key_passphrase_file.as_deref(),
+            self.key_passcommand.as_deref(),
+            self.key_passphrase_prompt
+                .then_some("Enter key passphrase: "),
+        )
+    }
+}
+
+// Reads a secret from the first source given: an environment variable, a
+// file, a command or a prompt with the given text. Returns None if no
+// source was given.
+pub fn read_source(
+    variable: Option<&str>,
+    path: Option<&str>,
+    passcommand: Option<&str>,
+    prompt: Option<&str>,
+) -> Result<Option<String>, PassphraseError> {
+    let passphrase = if let Some(variable) = variable {
+        read_env(variable)?
+    } else if let Some(path) = path {
+        read_file(path)?
+    } else if let Some(passcommand) = passcommand {
+        run_passcommand(passcommand)?
+    } else if let Some(prompt) = prompt {
+        rpassword::prompt_password(prompt).map_err(PassphraseError::PromptError)?
+    } else {
+        return Ok(None);
+    };
+
+    match passphrase.is_empty() {
+        true => Err(PassphraseError::EmptyPassphrase),
+        false => Ok(Some(passphrase)),
+    }
+}
+
+// Removes the trailing newline left by files and command output,
+// any other whitespace is kept as part of the passphrase.
+fn strip_newline(value: &str) -> String {
+    value
+        .strip_suffix('\n')
+        .map(|n| n.strip_suffix('\r').unwrap_or(n))
+        .unwrap_or(value)
+        .to_owned()
+}
+
+fn read_env(variable: &str) -> Result<String, PassphraseError> {
+    env::var(variable).map_err(|_| PassphraseError::EnvironmentVariableMissing(variable.to_owned()))
+}
+
+// Reads the passphrase from a file, refusing files that can be read or
+// written by the group or other users.
+fn read_file(path: &str) -> Result<String, PassphraseError> {
+    let metadata =
+        fs::metadata(path).map_err(|e| PassphraseError::FileReadError(path.to_owned(), e))?;
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+        return Err(PassphraseError::InsecurePermissions(path.to_owned(), mode));
+    }
+
+    let contents =
+        fs::read_to_string(path).map_err(|e| PassphraseError::FileReadError(path.to_owned(), e))?;
+    Ok(strip_newline(&contents))
+}
+
+// Runs the passcommand through the shell and uses its standard output
+// as the passphrase, standard error is left attached to the terminal.
+fn run_passcommand(passcommand: &str) -> Result<String, PassphraseError> {
+    let output = Command::new("sh")
+        .args(["-c", passcommand])
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| PassphraseError::CommandFailed(passcommand.to_owned(), e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(PassphraseError::CommandFailed(
+            passcommand.to_owned(),
+            output.status.to_string(),
+        ));
+    }
+
+    let stdout = String::from_utf8(output.stdout).map_err(|_| {
+        PassphraseError::CommandFailed(passcommand.to_owned(), "output is not UTF-8".to_owned())
+    })?;
+    Ok(strip_newline(&stdout))
+}
+
+// Reads the passphrase from the selected source of the arguments and
+// stores it, leaving the arguments unchanged if no source was selected.
+pub fn resolve_passphrase<T: PassphraseTrait>(args: T) -> Result<T, PassphraseError> {
+    match args.passphrase_source().read()? {
+        Some(passphrase) => Ok(args.with_passphrase(passphrase)),
+        None => Ok(args),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn setup_passphrase_file(contents: &str, mode: u32) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        fs::set_permissions(file.path(), fs::Permissions::from_mode(mode)).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_strip_newline() {
+        assert_eq!(strip_newline("passphrase\n"), "passphrase");
+        assert_eq!(strip_newline("passphrase\r\n"), "passphrase");
+        assert_eq!(strip_newline("passphrase \n\n"), "passphrase \n");
+        assert_eq!(strip_newline("passphrase"), "passphrase");
+    }
+
+    #[test]
+    fn test_read_none() {
+        let passphrase_args = PassphraseArgs::default();
+        assert_eq!(passphrase_args.read().unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_env() {
+        env::set_var("RUSTY_BORG_TEST_PASSPHRASE", "passphrase");
+        let passphrase_args = PassphraseArgs {
+            passphrase_env: Some("RUSTY_BORG_TEST_PASSPHRASE".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            passphrase_args.read().unwrap(),
+            Some("passphrase".to_owned())
+        );
+
+        let passphrase_args = PassphraseArgs {
+            passphrase_env: Some("RUSTY_BORG_TEST_MISSING".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            passphrase_args.read(),
+            Err(PassphraseError::EnvironmentVariableMissing(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_file_pass() {
+        let file = setup_passphrase_file("passphrase\n", 0o600);
+        let passphrase_args = PassphraseArgs {
+            passphrase_file: Some(file.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            passphrase_args.read().unwrap(),
+            Some("passphrase".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_read_file_permissions_fail() {
+        let file = setup_passphrase_file("passphrase\n", 0o644);
+        let passphrase_args = PassphraseArgs {
+            passphrase_file: Some(file.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        match passphrase_args.read() {
+            Err(PassphraseError::InsecurePermissions(_, mode)) => assert_eq!(mode, 0o644),
+            _ => panic!("Expected an insecure permissions error"),
+        }
+    }
+
+    #[test]
+    fn test_read_file_empty_fail() {
+        let file = setup_passphrase_file("\n", 0o600);
+        let passphrase_args = PassphraseArgs {
+            passphrase_file: Some(file.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            passphrase_args.read(),
+            Err(PassphraseError::EmptyPassphrase)
+        ));
+    }
+
+    #[test]
+    fn test_read_key_passphrase() {
+        assert_eq!(KeyPassphraseArgs::default().read().unwrap(), None);
+
+        let key_passphrase_args = KeyPassphraseArgs {
+            key_passcommand: Some("echo key passphrase".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            key_passphrase_args.read().unwrap(),
+            Some("key passphrase".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_run_passcommand() {
+        let passphrase_args = PassphraseArgs {
+            passcommand: Some("echo passphrase".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            passphrase_args.read().unwrap(),
+            Some("passphrase".to_owned())
+        );
+
+        let passphrase_args = PassphraseArgs {
+            passcommand: Some("exit 3".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            passphrase_args.read(),
+            Err(PassphraseError::CommandFailed(_, _))
+        ));
+    }
+}
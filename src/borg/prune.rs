@@ -0,0 +1,344 @@
+use super::super::util;
+use super::command::{borg_command, execute_borg};
+use super::errors::RetentionError;
+use super::passphrase::{PassphraseArgs, PassphraseTrait};
+use super::remote::RemoteArgs;
+use super::{BorgTrait, PruneTrait};
+use borgbackup::common::{PruneWithin, PruneWithinTime};
+use clap::{Args, Parser};
+use std::num::NonZeroU16;
+
+// Struct for managing the retention policy used when pruning archives,
+// shared by any command that applies a retention policy.
+#[derive(Debug, Clone, Args, Default)]
+pub struct RetentionArgs {
+    #[arg(long, value_parser = parse_keep_within)]
+    pub keep_within: Option<PruneWithin>,
+    #[arg(long)]
+    pub keep_last: Option<NonZeroU16>,
+    #[arg(long)]
+    pub keep_hourly: Option<NonZeroU16>,
+    #[arg(long)]
+    pub keep_daily: Option<NonZeroU16>,
+    #[arg(long)]
+    pub keep_weekly: Option<NonZeroU16>,
+    #[arg(long)]
+    pub keep_monthly: Option<NonZeroU16>,
+    #[arg(long)]
+    pub keep_yearly: Option<NonZeroU16>,
+    #[arg(long)]
+    pub glob_archives: Option<String>,
+    #[arg(long, conflicts_with = "glob_archives")]
+    pub prefix: Option<String>,
+}
+
+impl RetentionArgs {
+    // Returns true if any of the `keep` rules have been set.
+    pub fn has_rules(&self) -> bool {
+        self.keep_within.is_some()
+            || [
+                self.keep_last,
+                self.keep_hourly,
+                self.keep_daily,
+                self.keep_weekly,
+                self.keep_monthly,
+                self.keep_yearly,
+            ]
+            .iter()
+            .any(Option::is_some)
+    }
+
+    // Converts the retention policy into borg `prune` arguments.
+    // A prefix is converted into a glob matching every archive starting with it.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(n) = self.keep_within {
+            args.push(format!("--keep-within={}", n));
+        }
+        let keep_rules = [
+            ("--keep-last", self.keep_last),
+            ("--keep-hourly", self.keep_hourly),
+            ("--keep-daily", self.keep_daily),
+            ("--keep-weekly", self.keep_weekly),
+            ("--keep-monthly", self.keep_monthly),
+            ("--keep-yearly", self.keep_yearly),
+        ];
+        for (flag, value) in keep_rules {
+            if let Some(n) = value {
+                args.push(format!("{}={}", flag, n));
+            }
+        }
+
+        let glob_archives = match (&self.glob_archives, &self.prefix) {
+            (Some(glob), _) => Some(glob.to_owned()),
+            (None, Some(prefix)) => Some(format!("{}*", prefix)),
+            (None, None) => None,
+        };
+        if let Some(n) = glob_archives {
+            args.push(format!("--glob-archives={}", n));
+        }
+        args
+    }
+}
+
+// Struct for managing the necessary arguments for pruning a repository.
+#[derive(Debug, Clone, Parser)]
+pub struct PruneArgs {
+    repository: String,
+    #[arg(short, long)]
+    passphrase: Option<String>,
+    #[command(flatten)]
+    passphrase_source: PassphraseArgs,
+    #[command(flatten)]
+    remote: RemoteArgs,
+    #[command(flatten)]
+    retention: RetentionArgs,
+    #[arg(short, long, default_value_t = false)]
+    dry_run: bool,
+}
+
+impl BorgTrait for PruneArgs {
+    fn repository(&self) -> String {
+        self.repository.to_owned()
+    }
+
+    fn passphrase(&self) -> Option<String> {
+        self.passphrase.to_owned()
+    }
+
+    fn remote(&self) -> RemoteArgs {
+        self.remote.to_owned()
+    }
+}
+
+impl PassphraseTrait for PruneArgs {
+    fn passphrase_source(&self) -> &PassphraseArgs {
+        &self.passphrase_source
+    }
+
+    fn with_passphrase(self, passphrase: String) -> Self {
+        PruneArgs {
+            passphrase: Some(passphrase),
+            ..self
+        }
+    }
+}
+
+impl PruneTrait for PruneArgs {
+    fn retention(&self) -> RetentionArgs {
+        self.retention.to_owned()
+    }
+
+    fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+}
+
+// The archives kept and removed (or that would be removed in a dry run)
+// by a prune operation.
+#[derive(Debug, Clone, Default)]
+pub struct PruneResult {
+    pub kept: Vec<String>,
+    pub pruned: Vec<String>,
+    pub dry_run: bool,
+}
+
+// Parses a `--keep-within` interval such as `7d` where the unit is
+// one of H (hours), d (days), w (weeks), m (months) or y (years).
+fn parse_keep_within(keep_within: &str) -> Result<PruneWithin, String> {
+    let invalid = || {
+        format!(
+            "Invalid interval '{}', expected a value such as 7d",
+            keep_within
+        )
+    };
+
+    let unit = keep_within.chars().last().ok_or_else(invalid)?;
+    let time = match unit {
+        'H' => PruneWithinTime::Hour,
+        'd' => PruneWithinTime::Day,
+        'w' => PruneWithinTime::Week,
+        'm' => PruneWithinTime::Month,
+        'y' => PruneWithinTime::Year,
+        _ => return Err(invalid()),
+    };
+    let quantifier = keep_within[..keep_within.len() - unit.len_utf8()]
+        .parse::<NonZeroU16>()
+        .map_err(|_| invalid())?;
+
+    Ok(PruneWithin { quantifier, time })
+}
+
+// Splits the archive listed by `borg prune --list` from the action in front of it,
+// e.g. `Keeping archive (rule: daily #1): <archive>` or `Would prune: <archive>`.
+fn parse_prune_list(lines: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut kept = Vec::new();
+    let mut pruned = Vec::new();
+
+    for line in lines {
+        let label_end = match line.find("):") {
+            Some(n) if line.starts_with("Keeping") => n + 2,
+            _ => match line.find(':') {
+                Some(n) => n + 1,
+                None => continue,
+            },
+        };
+        let archive = line[label_end..].trim().to_owned();
+
+        if line.starts_with("Keeping") {
+            kept.push(archive);
+        } else if line.starts_with("Would prune") || line.starts_with("Pruning") {
+            pruned.push(archive);
+        }
+    }
+    (kept, pruned)
+}
+
+pub fn display_prune_info(prune_result: PruneResult) {
+    let action = if prune_result.dry_run {
+        "Would prune"
+    } else {
+        "Pruned"
+    };
+    for archive in prune_result.pruned.iter() {
+        util::log_print(&format!("{}: {}", action, archive), util::LogLevel::Info);
+    }
+    util::log_print(
+        &format!(
+            "{} {} archive(s), kept {} archive(s)",
+            action,
+            prune_result.pruned.len(),
+            prune_result.kept.len()
+        ),
+        util::LogLevel::Info,
+    );
+}
+
+// This is the entrypoint of the **prune** module where a variable implementing
+// PruneTrait is passed containing the repository and retention policy.
+//
+// At least one `keep` rule is required so a repository is never emptied
+// by accident. The archives borg keeps and removes are parsed from its
+// `--list` output, and in a dry run nothing is removed from the repository.
+pub fn prune_archives(prune_args: &impl PruneTrait) -> Result<PruneResult, RetentionError> {
+    let retention = prune_args.retention();
+    if !retention.has_rules() {
+        return Err(RetentionError::NoRetentionRules);
+    }
+
+    let mut args = vec!["prune".to_owned(), "--list".to_owned()];
+    if prune_args.dry_run() {
+        args.push("--dry-run".to_owned());
+    }
+    args.extend(retention.to_args());
+    args.push(prune_args.repository());
+
+    let command = borg_command(args, prune_args.passphrase(), &prune_args.common_options());
+    let output = execute_borg(command).map_err(RetentionError::PruneCommandError)?;
+    let (kept, pruned) = parse_prune_list(output.messages_from("borg.output.list"));
+
+    Ok(PruneResult {
+        kept,
+        pruned,
+        dry_run: prune_args.dry_run(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup_prune_args() -> PruneArgs {
+        PruneArgs {
+            repository: "repository".to_owned(),
+            passphrase: Some("passphrase".to_owned()),
+            passphrase_source: PassphraseArgs::default(),
+            remote: RemoteArgs::default(),
+            retention: RetentionArgs::default(),
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn test_get_repository() {
+        let prune_args = setup_prune_args();
+        assert_eq!(prune_args.repository(), "repository");
+    }
+
+    #[test]
+    fn test_get_passphrase() {
+        let prune_args = setup_prune_args();
+        assert_eq!(prune_args.passphrase(), Some("passphrase".to_owned()));
+    }
+
+    #[test]
+    fn test_has_rules() {
+        let mut retention = RetentionArgs::default();
+        assert!(!retention.has_rules());
+
+        retention.prefix = Some("prefix".to_owned());
+        assert!(!retention.has_rules());
+
+        retention.keep_daily = NonZeroU16::new(7);
+        assert!(retention.has_rules());
+    }
+
+    #[test]
+    fn test_to_args() {
+        let retention = RetentionArgs {
+            keep_within: Some(parse_keep_within("2d").unwrap()),
+            keep_daily: NonZeroU16::new(7),
+            keep_monthly: NonZeroU16::new(6),
+            prefix: Some("host-".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            retention.to_args(),
+            vec![
+                "--keep-within=2d",
+                "--keep-daily=7",
+                "--keep-monthly=6",
+                "--glob-archives=host-*"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_keep_within() {
+        assert_eq!(parse_keep_within("12H").unwrap().to_string(), "12H");
+        assert_eq!(parse_keep_within("1y").unwrap().to_string(), "1y");
+        assert!(parse_keep_within("").is_err());
+        assert!(parse_keep_within("d").is_err());
+        assert!(parse_keep_within("0d").is_err());
+        assert!(parse_keep_within("7x").is_err());
+    }
+
+    #[test]
+    fn test_parse_prune_list() {
+        let lines = vec![
+            "Keeping archive (rule: daily #1):        second    Tue, 2025-01-07 00:00:00 [aa]"
+                .to_owned(),
+            "Would prune:                             first     Mon, 2025-01-06 00:00:00 [bb]"
+                .to_owned(),
+            "Pruning archive (1/1):                   zeroth    Sun, 2025-01-05 00:00:00 [cc]"
+                .to_owned(),
+        ];
+        let (kept, pruned) = parse_prune_list(lines);
+
+        assert_eq!(kept, vec!["second    Tue, 2025-01-07 00:00:00 [aa]"]);
+        assert_eq!(
+            pruned,
+            vec![
+                "first     Mon, 2025-01-06 00:00:00 [bb]",
+                "zeroth    Sun, 2025-01-05 00:00:00 [cc]"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prune_archives_no_rules_fail() {
+        let prune_args = setup_prune_args();
+        let result = prune_archives(&prune_args);
+        assert!(matches!(result, Err(RetentionError::NoRetentionRules)));
+    }
+}
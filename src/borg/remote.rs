@@ -0,0 +1,978 @@
+use super::errors::RemoteError;
+use super::BorgTrait;
+use borgbackup::common::CommonOptions;
+use clap::{Args, ValueEnum};
+use ssh2::{CheckResult, HashType, HostKeyType, KnownHostFileKind, Session};
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const DEFAULT_SSH_PORT: u16 = 22;
+const DEFAULT_RSH: &str = "ssh";
+pub const DEFAULT_REMOTE_PATH: &str = "borg";
+const SUPPORTED_BORG_MAJOR_VERSION: u32 = 1;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_IDENTITIES: [&str; 3] = ["id_rsa", "id_ecdsa", "id_ed25519"];
+
+// Struct for managing how borg reaches a remote repository. The settings
+// are passed to borg through CommonOptions, and unless `--skip-preflight`
+// is given the remote host is checked before borg is run.
+#[derive(Debug, Clone, Args, Default)]
+pub struct RemoteArgs {
+    #[arg(long, value_name = "COMMAND")]
+    pub rsh: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    pub remote_path: Option<String>,
+    #[arg(long, value_name = "FILE")]
+    pub ssh_key: Option<String>,
+    #[arg(long, default_value_t = false)]
+    pub skip_preflight: bool,
+}
+
+impl RemoteArgs {
+    // The CommonOptions for borg, using `ssh -i <SSH_KEY>` as the remote
+    // shell when a key is given without `--rsh`.
+    pub fn common_options(&self) -> CommonOptions {
+        let rsh = self.rsh.to_owned().or_else(|| {
+            self.ssh_key
+                .as_ref()
+                .map(|n| format!("ssh -i '{}'", n.replace('\'', r"'\''")))
+        });
+        CommonOptions {
+            rsh,
+            remote_path: self.remote_path.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    // Converts the settings back into command line options, used when
+    // another RustyBorg command is generated such as in systemd units.
+    pub fn to_args(&self) -> Vec<String> {
+        let options = [
+            ("--rsh", &self.rsh),
+            ("--remote-path", &self.remote_path),
+            ("--ssh-key", &self.ssh_key),
+        ];
+        let mut args: Vec<String> = options
+            .into_iter()
+            .filter_map(|(option, value)| value.as_ref().map(|n| format!("{}={}", option, n)))
+            .collect();
+        if self.skip_preflight {
+            args.push("--skip-preflight".to_owned());
+        }
+        args
+    }
+
+    pub fn remote_path(&self) -> String {
+        self.remote_path
+            .to_owned()
+            .unwrap_or(DEFAULT_REMOTE_PATH.to_owned())
+    }
+}
+
+// A repository given as `ssh://[user@]host[:port]/path`, or in the scp
+// style `[user@]host:path` borg 1.x also accepts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteRepository {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl RemoteRepository {
+    // Parses a repository, returning None when it isn't an `ssh://` URL or
+    // scp style location so local repositories are left to borg.
+    pub fn parse(repository: &str) -> Result<Option<RemoteRepository>, RemoteError> {
+        let invalid =
+            |reason: &str| RemoteError::InvalidRepository(repository.to_owned(), reason.to_owned());
+        let rest = match repository.strip_prefix("ssh://") {
+            Some(n) => n,
+            None => return RemoteRepository::parse_scp(repository),
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(n) => rest.split_at(n),
+            None => return Err(invalid("expected a path after the host")),
+        };
+        if path.len() < 2 {
+            return Err(invalid("expected a path after the host"));
+        }
+
+        let (user, address) = match authority.rsplit_once('@') {
+            Some(("", _)) => return Err(invalid("the user is empty")),
+            Some((user, address)) => (Some(user.to_owned()), address),
+            None => (None, authority),
+        };
+
+        // IPv6 addresses are written in brackets, e.g. `[::1]:22`
+        let (host, port) = match address.strip_prefix('[') {
+            Some(n) => match n.split_once(']') {
+                Some((host, port)) => (host, port.strip_prefix(':')),
+                None => return Err(invalid("missing ']' after the IPv6 address")),
+            },
+            None => match address.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (address, None),
+            },
+        };
+        if host.is_empty() {
+            return Err(invalid("the host is empty"));
+        }
+        let port = match port {
+            Some(n) => n.parse().map_err(|_| invalid("the port is invalid"))?,
+            None => DEFAULT_SSH_PORT,
+        };
+
+        Ok(Some(RemoteRepository {
+            user,
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        }))
+    }
+
+    // Parses an scp style location, `[user@]host:path`. Like borg, anything
+    // with a `/` before the first `:` is a local path, as is a `file://` URL.
+    fn parse_scp(repository: &str) -> Result<Option<RemoteRepository>, RemoteError> {
+        let invalid =
+            |reason: &str| RemoteError::InvalidRepository(repository.to_owned(), reason.to_owned());
+        if repository.starts_with("file://") {
+            return Ok(None);
+        }
+
+        let (user, address) = match repository.split_once('@') {
+            Some((user, address)) if !user.contains(['/', ':']) => (Some(user), address),
+            _ => (None, repository),
+        };
+
+        // IPv6 addresses are written in brackets, e.g. `[::1]:repo`
+        let (host, path) = match address.strip_prefix('[') {
+            Some(n) => match n.split_once("]:") {
+                Some(n) => n,
+                None => return Ok(None),
+            },
+            None => match address.split_once(':') {
+                Some((host, path)) if !host.contains('/') => (host, path),
+                _ => return Ok(None),
+            },
+        };
+        if user == Some("") {
+            return Err(invalid("the user is empty"));
+        }
+        if host.is_empty() {
+            return Err(invalid("the host is empty"));
+        }
+        if path.is_empty() {
+            return Err(invalid("expected a path after the host"));
+        }
+
+        Ok(Some(RemoteRepository {
+            user: user.map(|n| n.to_owned()),
+            host: host.to_owned(),
+            port: DEFAULT_SSH_PORT,
+            path: path.to_owned(),
+        }))
+    }
+}
+
+// Opens a TCP connection to the host, failing after a timeout rather
+// than waiting on an unreachable host.
+pub fn connect(host: &str, port: u16) -> Result<TcpStream, RemoteError> {
+    let address = format!("{}:{}", host, port);
+    let socket_addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| RemoteError::ConnectionFailed(address.to_owned(), e))?;
+
+    let mut last_err = None;
+    for socket_addr in socket_addrs {
+        match TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT) {
+            Ok(n) => return Ok(n),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(RemoteError::ConnectionFailed(
+        address,
+        last_err.unwrap_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound)),
+    ))
+}
+
+// Starts an SSH session over the connection.
+pub fn handshake(tcp: TcpStream) -> Result<Session, RemoteError> {
+    let mut session = Session::new().map_err(RemoteError::SshError)?;
+    session.set_tcp_stream(tcp);
+    session.handshake().map_err(RemoteError::SshError)?;
+    Ok(session)
+}
+
+// How a host key missing from the known hosts file is handled. A key that
+// doesn't match the known one is always rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum HostKeyPolicy {
+    // Reject the host
+    #[default]
+    Strict,
+    // Trust the key on first use and add it to the known hosts file
+    AcceptNew,
+    // Report the fingerprint without rejecting the host
+    Fingerprint,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostKeyStatus {
+    Known,
+    Added,
+    Unverified,
+}
+
+// The host key presented by the server and how it compared to the known
+// hosts file.
+#[derive(Debug, Clone)]
+pub struct HostKey {
+    pub fingerprint: String,
+    pub status: HostKeyStatus,
+}
+
+// The known hosts file used by `ssh`, `~/.ssh/known_hosts`.
+pub fn default_known_hosts() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_default();
+    Path::new(&home).join(".ssh").join("known_hosts")
+}
+
+// The name of the host in a known hosts file, where OpenSSH writes hosts
+// on a non-standard port as `[host]:port`.
+fn known_hosts_name(host: &str, port: u16) -> String {
+    match port {
+        DEFAULT_SSH_PORT => host.to_owned(),
+        _ => format!("[{}]:{}", host, port),
+    }
+}
+
+// Formats a SHA256 host key hash as OpenSSH does, e.g. `SHA256:<base64>`
+// with the padding removed.
+fn fingerprint(hash: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in hash.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let value = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        (0..=chunk.len())
+            .for_each(|i| encoded.push(ALPHABET[(value >> (18 - 6 * i) & 0x3f) as usize] as char));
+    }
+    format!("SHA256:{}", encoded)
+}
+
+// Appends the host key to the known hosts file as a single line, leaving
+// the existing entries as they are.
+fn append_known_host(
+    session: &Session,
+    known_hosts_file: &Path,
+    name: &str,
+    (key, key_type): (&[u8], HostKeyType),
+) -> Result<(), RemoteError> {
+    let known_hosts_err = |e: &dyn fmt::Display| {
+        RemoteError::KnownHostsError(known_hosts_file.display().to_string(), e.to_string())
+    };
+    let mut known_hosts = session.known_hosts().map_err(|e| known_hosts_err(&e))?;
+    known_hosts
+        .add(name, key, name, key_type.into())
+        .map_err(|e| known_hosts_err(&e))?;
+    let host = known_hosts
+        .hosts()
+        .map_err(|e| known_hosts_err(&e))?
+        .pop()
+        .ok_or_else(|| known_hosts_err(&"the host key could not be added"))?;
+    let line = known_hosts
+        .write_string(&host, KnownHostFileKind::OpenSSH)
+        .map_err(|e| known_hosts_err(&e))?;
+
+    if let Some(n) = known_hosts_file.parent() {
+        fs::create_dir_all(n).map_err(|e| known_hosts_err(&e))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(known_hosts_file)
+        .map_err(|e| known_hosts_err(&e))?;
+    writeln!(file, "{}", line.trim_end()).map_err(|e| known_hosts_err(&e))
+}
+
+// Looks up the host key in the known hosts file, which is treated as empty
+// if it doesn't exist yet, and applies the policy when the host isn't found.
+fn check_known_hosts(
+    session: &Session,
+    known_hosts_file: &Path,
+    host: &str,
+    port: u16,
+    key: (&[u8], HostKeyType),
+    fingerprint: &str,
+    policy: HostKeyPolicy,
+) -> Result<HostKeyStatus, RemoteError> {
+    let known_hosts_err = |e: ssh2::Error| {
+        RemoteError::KnownHostsError(known_hosts_file.display().to_string(), e.to_string())
+    };
+    let mut known_hosts = session.known_hosts().map_err(known_hosts_err)?;
+    if known_hosts_file.exists() {
+        known_hosts
+            .read_file(known_hosts_file, KnownHostFileKind::OpenSSH)
+            .map_err(known_hosts_err)?;
+    }
+
+    let name = known_hosts_name(host, port);
+    match known_hosts.check_port(host, port, key.0) {
+        CheckResult::Match => Ok(HostKeyStatus::Known),
+        CheckResult::Mismatch => Err(RemoteError::HostKeyMismatch(name, fingerprint.to_owned())),
+        CheckResult::NotFound => match policy {
+            HostKeyPolicy::Strict => Err(RemoteError::UnknownHostKey(name, fingerprint.to_owned())),
+            HostKeyPolicy::Fingerprint => Ok(HostKeyStatus::Unverified),
+            HostKeyPolicy::AcceptNew => {
+                append_known_host(session, known_hosts_file, &name, key)?;
+                Ok(HostKeyStatus::Added)
+            }
+        },
+        CheckResult::Failure => Err(known_hosts_err(ssh2::Error::unknown())),
+    }
+}
+
+// Checks the host key of the session against the known hosts file before
+// any credentials are sent to the host.
+pub fn check_host_key(
+    session: &Session,
+    host: &str,
+    port: u16,
+    known_hosts_file: &Path,
+    policy: HostKeyPolicy,
+) -> Result<HostKey, RemoteError> {
+    let (Some(key), Some(hash)) = (session.host_key(), session.host_key_hash(HashType::Sha256))
+    else {
+        return Err(RemoteError::SshError(ssh2::Error::unknown()));
+    };
+    let fingerprint = fingerprint(hash);
+    let status = check_known_hosts(
+        session,
+        known_hosts_file,
+        host,
+        port,
+        key,
+        &fingerprint,
+        policy,
+    )?;
+    Ok(HostKey {
+        fingerprint,
+        status,
+    })
+}
+
+// The way a session was authenticated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthMethod {
+    Agent,
+    PublicKey(String),
+    Password,
+}
+
+impl fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthMethod::Agent => write!(f, "ssh-agent"),
+            AuthMethod::PublicKey(key_file) => write!(f, "key {}", key_file),
+            AuthMethod::Password => write!(f, "password"),
+        }
+    }
+}
+
+// The credentials to authenticate with, tried in the same order as `ssh`:
+// the keys held by the ssh-agent, the key files and then the password.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub use_agent: bool,
+    pub key_files: Vec<String>,
+    pub key_passphrase: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Credentials {
+    // The ssh-agent and the key file if one is given, otherwise the
+    // default identities.
+    pub fn new(key_file: Option<&str>) -> Credentials {
+        Credentials {
+            use_agent: true,
+            key_files: key_file
+                .map(|n| vec![n.to_owned()])
+                .unwrap_or_else(default_identities),
+            ..Default::default()
+        }
+    }
+
+    fn methods(&self) -> Vec<AuthMethod> {
+        let agent = self.use_agent.then_some(AuthMethod::Agent);
+        let key_files = self.key_files.iter().cloned().map(AuthMethod::PublicKey);
+        let password = self.password.as_ref().map(|_| AuthMethod::Password);
+        agent.into_iter().chain(key_files).chain(password).collect()
+    }
+}
+
+// The identities of `~/.ssh` that `ssh` uses by default and exist.
+pub fn default_identities() -> Vec<String> {
+    let ssh_directory = Path::new(&env::var("HOME").unwrap_or_default()).join(".ssh");
+    DEFAULT_IDENTITIES
+        .iter()
+        .map(|n| ssh_directory.join(n))
+        .filter(|n| n.is_file())
+        .map(|n| n.display().to_string())
+        .collect()
+}
+
+// Authenticates as the user with each of the credentials in turn,
+// returning the method that was accepted.
+pub fn authenticate(
+    session: &Session,
+    host: &str,
+    user: &str,
+    credentials: &Credentials,
+) -> Result<AuthMethod, RemoteError> {
+    let methods = credentials.methods();
+    for method in &methods {
+        let result = match method {
+            AuthMethod::Agent => session.userauth_agent(user),
+            AuthMethod::PublicKey(key_file) => session.userauth_pubkey_file(
+                user,
+                None,
+                Path::new(key_file),
+                credentials.key_passphrase.as_deref(),
+            ),
+            AuthMethod::Password => {
+                session.userauth_password(user, credentials.password.as_deref().unwrap_or_default())
+            }
+        };
+        if result.is_ok() && session.authenticated() {
+            return Ok(method.to_owned());
+        }
+    }
+    Err(RemoteError::AuthenticationFailed(
+        format!("{}@{}", user, host),
+        methods.iter().map(|n| n.to_string()).collect(),
+    ))
+}
+
+// Runs a command on the host, returning its exit status and `stdout`.
+fn exec(session: &Session, host: &str, command: &str) -> Result<(i32, String), RemoteError> {
+    let mut channel = session.channel_session().map_err(RemoteError::SshError)?;
+    channel.exec(command).map_err(RemoteError::SshError)?;
+
+    let mut stdout = String::new();
+    channel
+        .read_to_string(&mut stdout)
+        .map_err(|e| RemoteError::ConnectionFailed(host.to_owned(), e))?;
+    channel.wait_close().map_err(RemoteError::SshError)?;
+
+    let exit_status = channel.exit_status().map_err(RemoteError::SshError)?;
+    Ok((exit_status, stdout))
+}
+
+// Parses the output of `borg --version`, e.g. `borg 1.2.8`, returning the
+// version if its major version is supported.
+fn parse_borg_version(host: &str, output: &str) -> Result<String, RemoteError> {
+    let version = output
+        .split_whitespace()
+        .last()
+        .unwrap_or_default()
+        .to_owned();
+    match version.split('.').next().map(|n| n.parse::<u32>()) {
+        Some(Ok(SUPPORTED_BORG_MAJOR_VERSION)) => Ok(version),
+        _ => Err(RemoteError::UnsupportedBorgVersion(
+            host.to_owned(),
+            output.trim().to_owned(),
+        )),
+    }
+}
+
+// Runs `<remote_path> --version` on the host, returning the version of
+// borg if it is installed and compatible.
+pub fn remote_borg_version(
+    session: &Session,
+    host: &str,
+    remote_path: &str,
+) -> Result<String, RemoteError> {
+    let command = format!("{} --version", remote_path);
+    match exec(session, host, &command)? {
+        (0, stdout) => parse_borg_version(host, &stdout),
+        _ => Err(RemoteError::BorgNotFound(host.to_owned(), command)),
+    }
+}
+
+// Quotes a repository path for the remote shell. Borg's `/~/` and `/./`
+// prefixes are relative to the home directory.
+fn shell_path(path: &str) -> String {
+    let quote = |n: &str| format!("'{}'", n.replace('\'', r"'\''"));
+    match path.strip_prefix("/~/").or_else(|| path.strip_prefix("~/")) {
+        Some(n) => format!("\"$HOME\"/{}", quote(n)),
+        None => match path.strip_prefix("/./") {
+            Some(n) => quote(n),
+            None => quote(path),
+        },
+    }
+}
+
+// Checks the repository path can be written to on the host, or that its
+// parent directory can be when the repository doesn't exist yet.
+pub fn check_writable(session: &Session, host: &str, path: &str) -> Result<(), RemoteError> {
+    let command = format!(
+        "P={}; if [ -e \"$P\" ]; then test -w \"$P\"; else test -w \"$(dirname \"$P\")\"; fi",
+        shell_path(path)
+    );
+    match exec(session, host, &command)? {
+        (0, _) => Ok(()),
+        _ => Err(RemoteError::RepositoryNotWritable(
+            host.to_owned(),
+            path.to_owned(),
+        )),
+    }
+}
+
+// Builds the command borg runs to reach the repository through the remote
+// shell, `<RSH> [-p PORT] [USER@]HOST <REMOTE_PATH> --version`, so the check
+// goes through the same `ssh` options, config and keys as borg does.
+fn remote_shell_command(rsh: &str, repository: &RemoteRepository, remote_path: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.args(["-c", &format!("{} \"$@\"", rsh), "sh"]);
+    if repository.port != DEFAULT_SSH_PORT {
+        command.args(["-p", &repository.port.to_string()]);
+    }
+    match &repository.user {
+        Some(user) => command.arg(format!("{}@{}", user, repository.host)),
+        None => command.arg(&repository.host),
+    };
+    command.args([remote_path, "--version"]);
+    command
+}
+
+// Runs `borg --version` on the host through the remote shell, returning
+// the version of borg if it is installed and compatible.
+fn remote_shell_borg_version(
+    rsh: &str,
+    repository: &RemoteRepository,
+    remote_path: &str,
+) -> Result<String, RemoteError> {
+    let output = remote_shell_command(rsh, repository, remote_path)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| RemoteError::RemoteShellFailed(rsh.to_owned(), e.to_string()))?;
+
+    // Like `ssh`, 255 is a failure to reach the host rather than of borg
+    match output.status.code() {
+        Some(0) => parse_borg_version(&repository.host, &String::from_utf8_lossy(&output.stdout)),
+        Some(255) | None => Err(RemoteError::RemoteShellFailed(
+            rsh.to_owned(),
+            output.status.to_string(),
+        )),
+        Some(_) => Err(RemoteError::BorgNotFound(
+            repository.host.to_owned(),
+            format!("{} --version", remote_path),
+        )),
+    }
+}
+
+// Returns the remote shell borg uses, the one given with `--rsh` or
+// `--ssh-key`, otherwise `BORG_RSH` or plain `ssh`.
+fn remote_shell(remote_args: &RemoteArgs, borg_rsh: Option<String>) -> String {
+    remote_args
+        .common_options()
+        .rsh
+        .or(borg_rsh.filter(|n| !n.is_empty()))
+        .unwrap_or(DEFAULT_RSH.to_owned())
+}
+
+// Checks a remote repository can be used before borg is run: the host can
+// be reached and borg is installed. The check runs through the same remote
+// shell as borg, so it succeeds and fails as borg would. Returns the remote
+// borg version, or None for a local repository or when the check is skipped.
+pub fn preflight_check(args: &impl BorgTrait) -> Result<Option<String>, RemoteError> {
+    let remote_args = args.remote();
+    let repository = match RemoteRepository::parse(&args.repository())? {
+        Some(n) => n,
+        None => return Ok(None),
+    };
+    if remote_args.skip_preflight {
+        return Ok(None);
+    }
+
+    let rsh = remote_shell(&remote_args, env::var("BORG_RSH").ok());
+    remote_shell_borg_version(&rsh, &repository, &remote_args.remote_path()).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote_repository() {
+        let result1 = RemoteRepository::parse("ssh://backup@example.com:2222/srv/borg")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            result1,
+            RemoteRepository {
+                user: Some("backup".to_owned()),
+                host: "example.com".to_owned(),
+                port: 2222,
+                path: "/srv/borg".to_owned(),
+            }
+        );
+
+        let result2 = RemoteRepository::parse("ssh://example.com/./repo")
+            .unwrap()
+            .unwrap();
+        assert_eq!(result2.user, None);
+        assert_eq!(result2.port, DEFAULT_SSH_PORT);
+        assert_eq!(result2.path, "/./repo");
+
+        let result3 = RemoteRepository::parse("ssh://user@[::1]:22/repo")
+            .unwrap()
+            .unwrap();
+        assert_eq!(result3.host, "::1");
+        assert_eq!(result3.port, 22);
+
+        assert_eq!(RemoteRepository::parse("/srv/borg").unwrap(), None);
+
+        let result4 = RemoteRepository::parse("backup@example.com:srv/borg")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            result4,
+            RemoteRepository {
+                user: Some("backup".to_owned()),
+                host: "example.com".to_owned(),
+                port: DEFAULT_SSH_PORT,
+                path: "srv/borg".to_owned(),
+            }
+        );
+
+        let result5 = RemoteRepository::parse("alias:/srv/borg").unwrap().unwrap();
+        assert_eq!(result5.user, None);
+        assert_eq!(result5.host, "alias");
+        assert_eq!(result5.path, "/srv/borg");
+
+        let result6 = RemoteRepository::parse("[::1]:repo").unwrap().unwrap();
+        assert_eq!(result6.host, "::1");
+        assert_eq!(result6.path, "repo");
+
+        assert_eq!(RemoteRepository::parse("./repo:1").unwrap(), None);
+        assert_eq!(RemoteRepository::parse("/srv/user@host:1").unwrap(), None);
+        assert_eq!(RemoteRepository::parse("file:///srv/borg").unwrap(), None);
+        assert_eq!(RemoteRepository::parse("repo").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_remote_repository_fail() {
+        assert!(RemoteRepository::parse("ssh://example.com").is_err());
+        assert!(RemoteRepository::parse("ssh://example.com/").is_err());
+        assert!(RemoteRepository::parse("ssh://@example.com/repo").is_err());
+        assert!(RemoteRepository::parse("ssh://example.com:ssh/repo").is_err());
+        assert!(RemoteRepository::parse("ssh://:22/repo").is_err());
+        assert!(RemoteRepository::parse("example.com:").is_err());
+        assert!(RemoteRepository::parse("@example.com:repo").is_err());
+        assert!(RemoteRepository::parse(":repo").is_err());
+    }
+
+    #[test]
+    fn test_common_options() {
+        let remote_args = RemoteArgs {
+            ssh_key: Some("/root/.ssh/id_ed25519".to_owned()),
+            remote_path: Some("/usr/local/bin/borg".to_owned()),
+            ..Default::default()
+        };
+        let common_options = remote_args.common_options();
+        assert_eq!(
+            common_options.rsh,
+            Some("ssh -i '/root/.ssh/id_ed25519'".to_owned())
+        );
+        assert_eq!(
+            common_options.remote_path,
+            Some("/usr/local/bin/borg".to_owned())
+        );
+
+        let remote_args = RemoteArgs {
+            rsh: Some("ssh -p 2222".to_owned()),
+            ssh_key: Some("key".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            remote_args.common_options().rsh,
+            Some("ssh -p 2222".to_owned())
+        );
+        assert_eq!(RemoteArgs::default().common_options().rsh, None);
+    }
+
+    #[test]
+    fn test_to_args() {
+        let remote_args = RemoteArgs {
+            rsh: Some("ssh -p 2222".to_owned()),
+            remote_path: Some("/usr/local/bin/borg".to_owned()),
+            ssh_key: None,
+            skip_preflight: true,
+        };
+        assert_eq!(
+            remote_args.to_args(),
+            [
+                "--rsh=ssh -p 2222",
+                "--remote-path=/usr/local/bin/borg",
+                "--skip-preflight"
+            ]
+        );
+        assert!(RemoteArgs::default().to_args().is_empty());
+    }
+
+    #[test]
+    fn test_parse_borg_version() {
+        assert_eq!(parse_borg_version("host", "borg 1.2.8\n").unwrap(), "1.2.8");
+        assert!(matches!(
+            parse_borg_version("host", "borg 2.0.0b14"),
+            Err(RemoteError::UnsupportedBorgVersion(_, _))
+        ));
+        assert!(parse_borg_version("host", "").is_err());
+    }
+
+    #[test]
+    fn test_shell_path() {
+        assert_eq!(shell_path("/srv/borg"), "'/srv/borg'");
+        assert_eq!(shell_path("/~/borg"), "\"$HOME\"/'borg'");
+        assert_eq!(shell_path("~/borg"), "\"$HOME\"/'borg'");
+        assert_eq!(shell_path("/./borg"), "'borg'");
+        assert_eq!(shell_path("/srv/it's"), r"'/srv/it'\''s'");
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(fingerprint(b""), "SHA256:");
+        assert_eq!(fingerprint(b"f"), "SHA256:Zg");
+        assert_eq!(fingerprint(b"fo"), "SHA256:Zm8");
+        assert_eq!(fingerprint(b"foobar"), "SHA256:Zm9vYmFy");
+        assert_eq!(fingerprint(&[0xff; 32]).len(), "SHA256:".len() + 43);
+    }
+
+    #[test]
+    fn test_known_hosts_name() {
+        assert_eq!(known_hosts_name("example.com", 22), "example.com");
+        assert_eq!(known_hosts_name("example.com", 2222), "[example.com]:2222");
+    }
+
+    #[test]
+    fn test_check_known_hosts() {
+        let session = Session::new().unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        let known_hosts_file = directory.path().join("ssh").join("known_hosts");
+        let key = (b"host key".as_slice(), HostKeyType::Ed255219);
+        let check = |port, key, policy| {
+            check_known_hosts(
+                &session,
+                &known_hosts_file,
+                "example.com",
+                port,
+                key,
+                "SHA256:fingerprint",
+                policy,
+            )
+        };
+
+        assert!(matches!(
+            check(2222, key, HostKeyPolicy::Strict),
+            Err(RemoteError::UnknownHostKey(_, _))
+        ));
+        assert_eq!(
+            check(2222, key, HostKeyPolicy::Fingerprint).unwrap(),
+            HostKeyStatus::Unverified
+        );
+        assert!(!known_hosts_file.exists());
+
+        assert_eq!(
+            check(2222, key, HostKeyPolicy::AcceptNew).unwrap(),
+            HostKeyStatus::Added
+        );
+        let contents = fs::read_to_string(&known_hosts_file).unwrap();
+        assert!(contents.starts_with("[example.com]:2222 ssh-ed25519 "));
+        assert_eq!(
+            check(2222, key, HostKeyPolicy::Strict).unwrap(),
+            HostKeyStatus::Known
+        );
+
+        let other_key = (b"other key".as_slice(), HostKeyType::Ed255219);
+        assert!(matches!(
+            check(2222, other_key, HostKeyPolicy::AcceptNew),
+            Err(RemoteError::HostKeyMismatch(_, _))
+        ));
+        assert!(matches!(
+            check(22, key, HostKeyPolicy::Strict),
+            Err(RemoteError::UnknownHostKey(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_credentials_methods() {
+        let credentials = Credentials {
+            use_agent: true,
+            key_files: vec!["/root/.ssh/id_ed25519".to_owned()],
+            key_passphrase: Some("passphrase".to_owned()),
+            password: Some("password".to_owned()),
+        };
+        assert_eq!(
+            credentials.methods(),
+            [
+                AuthMethod::Agent,
+                AuthMethod::PublicKey("/root/.ssh/id_ed25519".to_owned()),
+                AuthMethod::Password
+            ]
+        );
+        assert!(Credentials::default().methods().is_empty());
+
+        let credentials = Credentials::new(Some("key"));
+        assert_eq!(
+            credentials.methods(),
+            [AuthMethod::Agent, AuthMethod::PublicKey("key".to_owned())]
+        );
+        assert_eq!(
+            AuthMethod::PublicKey("/root/.ssh/id_rsa".to_owned()).to_string(),
+            "key /root/.ssh/id_rsa"
+        );
+    }
+
+    #[test]
+    fn test_remote_shell_command() {
+        let repository = RemoteRepository::parse("ssh://backup@example.com:2222/srv/borg")
+            .unwrap()
+            .unwrap();
+        let command = remote_shell_command("ssh -i key", &repository, "borg");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "-c",
+                "ssh -i key \"$@\"",
+                "sh",
+                "-p",
+                "2222",
+                "backup@example.com",
+                "borg",
+                "--version"
+            ]
+        );
+
+        let repository = RemoteRepository::parse("ssh://example.com/srv/borg")
+            .unwrap()
+            .unwrap();
+        let command = remote_shell_command("ssh", &repository, "/usr/local/bin/borg");
+        assert_eq!(
+            command.get_args().skip(3).collect::<Vec<_>>(),
+            ["example.com", "/usr/local/bin/borg", "--version"]
+        );
+    }
+
+    #[test]
+    fn test_remote_shell_borg_version() {
+        let repository = RemoteRepository::parse("ssh://backup@alias/srv/borg")
+            .unwrap()
+            .unwrap();
+
+        // The remote shell is given the host and command, and runs them
+        let rsh =
+            "sh -c 'test \"$1 $2 $3\" = \"backup@alias borg --version\" && echo borg 1.2.8' rsh";
+        assert_eq!(
+            remote_shell_borg_version(rsh, &repository, "borg").unwrap(),
+            "1.2.8"
+        );
+        assert!(matches!(
+            remote_shell_borg_version("sh -c 'exit 255' rsh", &repository, "borg"),
+            Err(RemoteError::RemoteShellFailed(_, _))
+        ));
+        assert!(matches!(
+            remote_shell_borg_version("sh -c 'exit 127' rsh", &repository, "borg"),
+            Err(RemoteError::BorgNotFound(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_remote_shell() {
+        let borg_rsh = Some("ssh -F borg_config".to_owned());
+        assert_eq!(remote_shell(&RemoteArgs::default(), None), "ssh");
+        assert_eq!(
+            remote_shell(&RemoteArgs::default(), borg_rsh.clone()),
+            "ssh -F borg_config"
+        );
+        assert_eq!(
+            remote_shell(&RemoteArgs::default(), Some(String::new())),
+            "ssh"
+        );
+
+        let remote_args = RemoteArgs {
+            rsh: Some("ssh -v".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(remote_shell(&remote_args, borg_rsh.clone()), "ssh -v");
+
+        let remote_args = RemoteArgs {
+            ssh_key: Some("key".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(remote_shell(&remote_args, borg_rsh), "ssh -i 'key'");
+    }
+
+    struct PreflightArgs(String, RemoteArgs);
+
+    impl BorgTrait for PreflightArgs {
+        fn repository(&self) -> String {
+            self.0.to_owned()
+        }
+
+        fn passphrase(&self) -> Option<String> {
+            None
+        }
+
+        fn remote(&self) -> RemoteArgs {
+            self.1.to_owned()
+        }
+    }
+
+    #[test]
+    fn test_preflight_check_rsh() {
+        // A host alias that only the remote shell can resolve
+        let remote_args = RemoteArgs {
+            rsh: Some("sh -c 'test \"$1\" = backup-host && echo borg 1.2.8' rsh".to_owned()),
+            ..Default::default()
+        };
+        let args = PreflightArgs("ssh://backup-host/srv/borg".to_owned(), remote_args.clone());
+        assert_eq!(preflight_check(&args).unwrap(), Some("1.2.8".to_owned()));
+
+        let args = PreflightArgs("backup-host:/srv/borg".to_owned(), remote_args);
+        assert_eq!(preflight_check(&args).unwrap(), Some("1.2.8".to_owned()));
+
+        let remote_args = RemoteArgs {
+            rsh: Some("sh -c 'exit 255' rsh".to_owned()),
+            ..Default::default()
+        };
+        let args = PreflightArgs("ssh://backup-host/srv/borg".to_owned(), remote_args);
+        assert!(preflight_check(&args).is_err());
+
+        let args = PreflightArgs("/srv/borg".to_owned(), RemoteArgs::default());
+        assert_eq!(preflight_check(&args).unwrap(), None);
+    }
+
+    #[test]
+    fn test_connect_fail() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        assert!(matches!(
+            connect("127.0.0.1", port),
+            Err(RemoteError::ConnectionFailed(_, _))
+        ));
+    }
+}
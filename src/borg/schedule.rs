@@ -1,19 +1,84 @@
-use chrono::Utc;
+use borgbackup::output::info::ArchiveStats;
+use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use cron::{self, Schedule};
 use daemonize::Daemonize;
-use std::fs::File;
+use serde::Serialize;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
-use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 
+use crate::config::{ConfigError, Profile, ProfileTrait};
+use crate::history::{self, HistoryError, RunError, RunRecord};
 use crate::util;
 
+use super::compact::{compact_repository, display_compact_info};
 use super::create::{create_archive, display_create_info};
-use super::errors::parse_archive_error;
-use super::init::initialise_repository;
+use super::errors::{ArchiveError, DaemonError, ScheduleError};
+use super::init::{initialise_repository, EncryptionType};
 use super::list::verify_repo_location;
-use super::{BorgTrait, CreateTrait};
+use super::passphrase::{PassphraseArgs, PassphraseTrait};
+use super::prune::{display_prune_info, prune_archives, RetentionArgs};
+use super::remote::{preflight_check, RemoteArgs};
+use super::{BorgTrait, CompactTrait, CreateTrait, InitTrait, PruneTrait};
+
+const DEFAULT_EXPRESSION: &str = "0 0 * * 1";
+const DEFAULT_TIMEZONE: &str = "Etc/UTC";
+const DEFAULT_JOB_NAME: &str = "default";
+const DEFAULT_PID_FILE: &str = "/tmp/rusty_borg.pid";
+const DEFAULT_LOG_DIRECTORY: &str = "/tmp";
+const DEFAULT_WORKING_DIRECTORY: &str = "/tmp";
+const STDOUT_LOG_FILE: &str = "rusty_borg.out";
+const STDERR_LOG_FILE: &str = "rusty_borg.err";
+
+// The crontab shortcuts accepted in place of a CRON expression.
+const SHORTCUTS: [(&str, &str); 7] = [
+    ("@yearly", "0 0 0 1 1 *"),
+    ("@annually", "0 0 0 1 1 *"),
+    ("@monthly", "0 0 0 1 * *"),
+    ("@weekly", "0 0 0 * * Sun"),
+    ("@daily", "0 0 0 * * *"),
+    ("@midnight", "0 0 0 * * *"),
+    ("@hourly", "0 0 * * * *"),
+];
+const DAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+// What to do with the runs a job missed while the scheduler wasn't running,
+// or the machine was asleep.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum MissedRunPolicy {
+    #[default]
+    Skip,
+    Once,
+    All,
+}
+
+impl MissedRunPolicy {
+    // Returns how many of the missed runs should be made up for.
+    fn runs(self, missed: usize) -> usize {
+        match self {
+            MissedRunPolicy::Skip => 0,
+            MissedRunPolicy::Once => missed.min(1),
+            MissedRunPolicy::All => missed,
+        }
+    }
+
+    // Returns the most recent of the missed runs that should be made up
+    // for, oldest first.
+    fn select(self, missed: Vec<DateTime<Utc>>) -> Vec<DateTime<Utc>> {
+        let skipped = missed.len() - self.runs(missed.len());
+        missed.into_iter().skip(skipped).collect()
+    }
+}
 
 #[derive(Debug, Clone, Parser)]
 pub struct ScheduleArgs {
@@ -23,17 +88,37 @@ pub struct ScheduleArgs {
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
 
-    #[arg(short, long, default_value = "0 0 * * 1")]
-    expression: String,
+    #[arg(long, default_value = DEFAULT_PID_FILE)]
+    pid_file: String,
 
-    #[arg(short, long, default_value = "Etc/UTC")]
-    timezone: String,
+    #[arg(long, default_value = DEFAULT_LOG_DIRECTORY)]
+    log_directory: String,
+
+    #[arg(long, default_value = DEFAULT_WORKING_DIRECTORY)]
+    working_directory: String,
+
+    #[arg(
+        short,
+        long,
+        help = "CRON expression: a 5 field crontab line with days of week 0-7 (0 and 7 are Sunday), \
+                6 or 7 fields with seconds first and days of week 1-7 (1 is Sunday), or a shortcut \
+                such as @daily. Day names like Mon work in both forms"
+    )]
+    expression: Option<String>,
 
     #[arg(short, long)]
-    repository: String,
+    timezone: Option<String>,
+
+    #[arg(short, long, required_unless_present_any = ["profile", "jobs", "preview"])]
+    repository: Option<String>,
 
     #[arg(short, long)]
-    passphrase: String,
+    passphrase: Option<String>,
+
+    #[command(flatten)]
+    passphrase_source: PassphraseArgs,
+    #[command(flatten)]
+    remote: RemoteArgs,
 
     #[arg(short, long)]
     archive: Option<String>,
@@ -46,16 +131,57 @@ pub struct ScheduleArgs {
 
     #[arg(long, num_args = 1.., value_delimiter = ' ')]
     exclude_patterns: Option<Vec<String>>,
+
+    #[command(flatten)]
+    retention: RetentionArgs,
+
+    #[arg(long, default_value_t = false)]
+    compact: bool,
+
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=99))]
+    compact_threshold: Option<u8>,
+
+    #[arg(long)]
+    profile: Option<String>,
+
+    #[arg(long, num_args = 1.., value_delimiter = ' ', conflicts_with = "profile")]
+    jobs: Vec<String>,
+
+    #[arg(long)]
+    history_file: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = MissedRunPolicy::Skip)]
+    missed_runs: MissedRunPolicy,
+
+    #[arg(long, value_name = "COUNT")]
+    preview: Option<usize>,
 }
 
 impl BorgTrait for ScheduleArgs {
     fn repository(&self) -> String {
-        self.repository.to_owned()
+        self.repository.to_owned().unwrap_or_default()
     }
 
-    fn passphrase(&self) -> String {
+    fn passphrase(&self) -> Option<String> {
         self.passphrase.to_owned()
     }
+
+    fn remote(&self) -> RemoteArgs {
+        self.remote.to_owned()
+    }
+}
+
+impl PassphraseTrait for ScheduleArgs {
+    fn passphrase_source(&self) -> &PassphraseArgs {
+        &self.passphrase_source
+    }
+
+    fn with_passphrase(self, passphrase: String) -> Self {
+        ScheduleArgs {
+            passphrase: Some(passphrase),
+            ..self
+        }
+    }
 }
 
 impl CreateTrait for ScheduleArgs {
@@ -76,74 +202,837 @@ impl CreateTrait for ScheduleArgs {
     }
 }
 
+impl InitTrait for ScheduleArgs {
+    fn encryption(&self) -> EncryptionType {
+        EncryptionType::KeyfileBlake2
+    }
+
+    fn append_only(&self) -> bool {
+        false
+    }
+
+    fn storage_quota(&self) -> Option<String> {
+        None
+    }
+
+    fn make_parent_dirs(&self) -> bool {
+        false
+    }
+}
+
+impl PruneTrait for ScheduleArgs {
+    fn retention(&self) -> RetentionArgs {
+        self.retention.to_owned()
+    }
+
+    fn dry_run(&self) -> bool {
+        false
+    }
+}
+
+impl CompactTrait for ScheduleArgs {
+    fn threshold(&self) -> Option<u8> {
+        self.compact_threshold
+    }
+}
+
+impl ProfileTrait for ScheduleArgs {
+    fn profile(&self) -> Option<String> {
+        self.profile.to_owned()
+    }
+
+    fn profile_options(&self) -> Vec<&'static str> {
+        let options = [
+            ("--repository", self.repository.is_some()),
+            ("--passphrase", self.passphrase.is_some()),
+            ("--archive", self.archive.is_some()),
+            ("--paths", !self.paths.is_empty()),
+            ("--include-patterns", self.include_patterns.is_some()),
+            ("--exclude-patterns", self.exclude_patterns.is_some()),
+            ("--expression", self.expression.is_some()),
+            ("--timezone", self.timezone.is_some()),
+        ];
+        options
+            .into_iter()
+            .filter(|(_, given)| *given)
+            .map(|(option, _)| option)
+            .collect()
+    }
+
+    fn apply_profile(self, name: &str, profile: &Profile) -> Result<Self, ConfigError> {
+        let repository = self
+            .repository
+            .or_else(|| profile.repository.to_owned())
+            .ok_or_else(|| ConfigError::MissingValue(name.to_owned(), "repository".to_owned()))?;
+        let paths = match self.paths.is_empty() {
+            true => profile.paths.to_owned(),
+            false => self.paths,
+        };
+
+        Ok(ScheduleArgs {
+            expression: self.expression.or_else(|| profile.expression.to_owned()),
+            timezone: self.timezone.or_else(|| profile.timezone.to_owned()),
+            repository: Some(repository),
+            passphrase: match self.passphrase {
+                Some(n) => Some(n),
+                None => profile.passphrase(name)?,
+            },
+            archive: self.archive.or_else(|| profile.archive.to_owned()),
+            paths,
+            include_patterns: self
+                .include_patterns
+                .or_else(|| profile.include_patterns.to_owned()),
+            exclude_patterns: self
+                .exclude_patterns
+                .or_else(|| profile.exclude_patterns.to_owned()),
+            profile: Some(name.to_owned()),
+            ..self
+        })
+    }
+}
+
 impl ScheduleArgs {
-    fn generate_expression(&self) -> Schedule {
-        cron::Schedule::from_str(&self.expression).expect("Failed to parse CRON expression")
+    // The options given that only affect the scheduler itself rather than
+    // the jobs it runs, so commands reusing these arguments to describe
+    // jobs, such as `systemd`, can reject them.
+    pub fn scheduler_options(&self) -> Vec<&'static str> {
+        let options = [
+            ("--daemonize", self.daemonize),
+            ("--verbose", self.verbose),
+            ("--pid-file", self.pid_file != DEFAULT_PID_FILE),
+            (
+                "--log-directory",
+                self.log_directory != DEFAULT_LOG_DIRECTORY,
+            ),
+            (
+                "--working-directory",
+                self.working_directory != DEFAULT_WORKING_DIRECTORY,
+            ),
+            ("--history-file", self.history_file.is_some()),
+            ("--preview", self.preview.is_some()),
+        ];
+        options
+            .into_iter()
+            .filter(|(_, given)| *given)
+            .map(|(option, _)| option)
+            .collect()
+    }
+
+    // The profiles to run as separate jobs, each is applied to a copy
+    // of these arguments.
+    pub fn jobs(&self) -> Vec<String> {
+        self.jobs.to_owned()
+    }
+
+    // Jobs are named after the profile they were loaded from.
+    pub(super) fn job_name(&self) -> String {
+        self.profile
+            .to_owned()
+            .unwrap_or(DEFAULT_JOB_NAME.to_owned())
+    }
+
+    fn history_path(&self) -> Result<std::path::PathBuf, HistoryError> {
+        history::history_path(self.history_file.to_owned())
+    }
+
+    // Resolves a relative `--history-file` against the current directory
+    // before a daemon moves into its working directory, so runs are recorded
+    // where `history --history-file` reads them from.
+    pub fn absolute_history_file(self) -> Result<ScheduleArgs, HistoryError> {
+        let history_file = match self.history_file {
+            Some(n) => Some(
+                std::path::absolute(&n)
+                    .map_err(|e| HistoryError::WriteError(PathBuf::from(&n), e))?
+                    .display()
+                    .to_string(),
+            ),
+            None => None,
+        };
+        Ok(ScheduleArgs {
+            history_file,
+            ..self
+        })
+    }
+
+    pub(super) fn compact(&self) -> bool {
+        self.compact
+    }
+
+    pub(super) fn missed_runs(&self) -> MissedRunPolicy {
+        self.missed_runs
+    }
+
+    pub(super) fn expression(&self) -> String {
+        self.expression
+            .to_owned()
+            .unwrap_or(DEFAULT_EXPRESSION.to_owned())
+    }
+
+    pub(super) fn timezone(&self) -> String {
+        self.timezone
+            .to_owned()
+            .unwrap_or(DEFAULT_TIMEZONE.to_owned())
+    }
+
+    // The number of upcoming runs to print instead of scheduling the jobs.
+    pub fn preview(&self) -> Option<usize> {
+        self.preview
+    }
+
+    // The CRON expression in the 6 or 7 field form used by the `cron` crate.
+    pub(super) fn schedule_expression(&self) -> Result<String, ScheduleError> {
+        normalise_expression(&self.expression())
+    }
+
+    fn generate_expression(&self) -> Result<Schedule, ScheduleError> {
+        cron::Schedule::from_str(&self.schedule_expression()?)
+            .map_err(|e| ScheduleError::InvalidExpression(self.expression(), e.to_string()))
     }
 
-    fn generate_timezone(&self) -> Tz {
-        self.timezone.parse().expect("Failed to parse timezone")
+    fn generate_timezone(&self) -> Result<Tz, ScheduleError> {
+        let timezone = self.timezone();
+        timezone
+            .parse()
+            .map_err(|_| ScheduleError::InvalidTimezone(timezone))
     }
+
+    // Checks the CRON expression and timezone can be parsed.
+    pub(super) fn validate(&self) -> Result<(), ScheduleError> {
+        self.generate_expression()?;
+        self.generate_timezone()?;
+        Ok(())
+    }
+}
+
+// Converts a crontab day of week to its name, as crontab numbers the days
+// from Sunday as 0 (or 7) while the `cron` crate starts from Sunday as 1.
+fn day_name(value: &str) -> Result<&'static str, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n <= 7 => Ok(DAY_NAMES[n % 7]),
+        _ => Err(format!("invalid day of week '{}'", value)),
+    }
+}
+
+// Converts the day of week field of a crontab line, keeping names and
+// wildcards as they are. A range ending on Sunday as 7 wraps around the
+// end of the week, so it is split in two.
+fn translate_day_of_week(field: &str) -> Result<String, String> {
+    let parts = field.split(',').map(|part| {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (part, None),
+        };
+        if range == "*" || range.chars().any(|c| c.is_ascii_alphabetic()) {
+            return Ok(part.to_owned());
+        }
+
+        let range = match range.split_once('-') {
+            Some(("0", "7")) => "Sun-Sat".to_owned(),
+            Some((start, "7")) => match step {
+                Some(_) => return Err(format!("unsupported day of week range '{}'", part)),
+                None => format!("{}-Sat,Sun", day_name(start)?),
+            },
+            Some((start, end)) => format!("{}-{}", day_name(start)?, day_name(end)?),
+            None => day_name(range)?.to_owned(),
+        };
+        Ok(match step {
+            Some(step) => format!("{}/{}", range, step),
+            None => range,
+        })
+    });
+    Ok(parts.collect::<Result<Vec<String>, String>>()?.join(","))
+}
+
+// Normalises a CRON expression into the 6 or 7 field form used by the
+// `cron` crate. Shortcuts such as `@daily` are expanded and classic 5 field
+// crontab lines run at the start of the minute, with their day of week
+// converted. Expressions with 6 or 7 fields are used as they are.
+//
+// A crontab line restricting both the day of month and day of week runs
+// when either matches, but the `cron` crate requires both, so such lines
+// are rejected rather than quietly running less often.
+fn normalise_expression(expression: &str) -> Result<String, ScheduleError> {
+    let invalid = |reason: String| ScheduleError::InvalidExpression(expression.to_owned(), reason);
+    let trimmed = expression.trim();
+
+    if trimmed.starts_with('@') {
+        return SHORTCUTS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(trimmed))
+            .map(|(_, n)| n.to_string())
+            .ok_or_else(|| invalid("unknown shortcut".to_owned()));
+    }
+
+    let fields: Vec<&str> = trimmed.split_whitespace().collect();
+    match fields.len() {
+        5 if !fields[2].starts_with(['*', '?']) && !fields[4].starts_with(['*', '?']) => {
+            Err(invalid(
+                "restricting both the day of month and day of week runs the job when either \
+                 matches in crontab, schedule them as separate jobs instead"
+                    .to_owned(),
+            ))
+        }
+        5 => Ok(format!(
+            "0 {} {}",
+            fields[..4].join(" "),
+            translate_day_of_week(fields[4]).map_err(invalid)?
+        )),
+        6 | 7 => Ok(fields.join(" ")),
+        n => Err(invalid(format!("expected 5, 6 or 7 fields, found {}", n))),
+    }
+}
+
+// The lock on the PID file, held for as long as the scheduler runs so only
+// one scheduler uses a PID file, whether in the foreground or daemonized.
+// The PID file is removed when the lock is dropped, on shutdown or when
+// starting the scheduler fails.
+struct PidLock {
+    path: PathBuf,
+    file: File,
 }
 
-fn daemonize_schedule() -> Result<(), Box<dyn std::error::Error>> {
-    let stdout = File::create("/tmp/rusty_borg.out")?;
-    let stderr = File::create("/tmp/rusty_borg.err")?;
+impl PidLock {
+    // Replaces the contents of the PID file with the current PID, called
+    // again after daemonizing as the daemon runs in another process.
+    fn write_pid(&mut self) -> Result<(), DaemonError> {
+        let path = self.path.display().to_string();
+        self.file
+            .set_len(0)
+            .and_then(|_| self.file.seek(SeekFrom::Start(0)))
+            .and_then(|_| writeln!(self.file, "{}", process::id()))
+            .map_err(|e| DaemonError::PidFileError(path, e))
+    }
+}
+
+impl Drop for PidLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            util::log_print(
+                &format!("Failed to remove {}: {}", self.path.display(), e),
+                util::LogLevel::Warn,
+            );
+        }
+    }
+}
+
+// Locks the PID file, failing if a live scheduler already holds the lock.
+// This is checked before daemonizing so a second daemon doesn't touch the
+// logs of the running one, a PID file left behind by a dead scheduler isn't
+// locked.
+fn check_single_instance(pid_file: &Path) -> Result<PidLock, DaemonError> {
+    let path = pid_file.display().to_string();
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(pid_file)
+        .map_err(|e| DaemonError::PidFileError(path.to_owned(), e))?;
+
+    match file.try_lock() {
+        Ok(()) => {
+            let mut pid_lock = PidLock {
+                path: pid_file.to_owned(),
+                file,
+            };
+            pid_lock.write_pid()?;
+            Ok(pid_lock)
+        }
+        Err(fs::TryLockError::WouldBlock) => {
+            let pid = fs::read_to_string(pid_file)
+                .ok()
+                .map(|n| n.trim().to_owned())
+                .filter(|n| !n.is_empty());
+            Err(DaemonError::AlreadyRunning(path, pid))
+        }
+        Err(fs::TryLockError::Error(e)) => Err(DaemonError::PidFileError(path, e)),
+    }
+}
+
+// Locks the PID file of the arguments, using its absolute path so it can
+// still be removed after daemonizing changes the working directory.
+fn lock_pid_file(schedule_args: &ScheduleArgs) -> Result<PidLock, DaemonError> {
+    let pid_file = std::path::absolute(&schedule_args.pid_file)
+        .map_err(|e| DaemonError::PidFileError(schedule_args.pid_file.to_owned(), e))?;
+    check_single_instance(&pid_file)
+}
+
+// Opens a daemon log file for appending, so the logs of previous runs are kept.
+fn open_log_file(log_directory: &Path, name: &str) -> Result<File, DaemonError> {
+    let path = log_directory.join(name);
+    fs::create_dir_all(log_directory)
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+        .map_err(|e| DaemonError::LogFileError(path.display().to_string(), e))
+}
+
+// Daemonizes the scheduler with the log directory and working directory
+// from the arguments. The daemon inherits the lock on the PID file, which
+// is then updated with the daemon's PID.
+fn daemonize_schedule(
+    schedule_args: &ScheduleArgs,
+    pid_lock: &mut PidLock,
+) -> Result<(), DaemonError> {
+    let log_directory = std::path::absolute(&schedule_args.log_directory)
+        .map_err(|e| DaemonError::LogFileError(schedule_args.log_directory.to_owned(), e))?;
+
+    let stdout = open_log_file(&log_directory, STDOUT_LOG_FILE)?;
+    let stderr = open_log_file(&log_directory, STDERR_LOG_FILE)?;
 
     let daemonize = Daemonize::new()
-        .pid_file("/tmp/rusty_borg.pid") // PID file
-        .chown_pid_file(true) // Change ownership of the PID file
-        .working_directory("/tmp") // for default behaviour.
-        .stdout(stdout) // Redirect stdout to `/tmp/daemon.out`.
-        .stderr(stderr); // Redirect stderr to `/tmp/daemon.err`.
+        .working_directory(&schedule_args.working_directory)
+        .stdout(stdout) // Append stdout to `<log_directory>/rusty_borg.out`.
+        .stderr(stderr); // Append stderr to `<log_directory>/rusty_borg.err`.
 
-    daemonize.start()?;
-    Ok(())
+    daemonize.start().map_err(DaemonError::DaemonizeError)?;
+    pid_lock.write_pid()
+}
+
+// Applies the retention policy after an archive has been created,
+// followed by compacting the repository if requested.
+//
+// Both steps are skipped when they aren't configured. A failure is always
+// logged so a job that stops pruning is noticed, while the outcome of a
+// successful step is only displayed in verbose mode.
+fn apply_retention(name: &str, schedule_args: &ScheduleArgs) {
+    if schedule_args.retention.has_rules() {
+        match prune_archives(schedule_args) {
+            Ok(n) => {
+                if schedule_args.verbose {
+                    display_prune_info(n)
+                }
+            }
+            Err(e) => util::log_print(
+                &format!("[{}] Prune failed: {}", name, e),
+                util::LogLevel::Error,
+            ),
+        }
+    }
+
+    if schedule_args.compact {
+        match compact_repository(schedule_args) {
+            Ok(n) => {
+                if schedule_args.verbose {
+                    display_compact_info(n)
+                }
+            }
+            Err(e) => util::log_print(
+                &format!("[{}] Compact failed: {}", name, e),
+                util::LogLevel::Error,
+            ),
+        }
+    }
+}
+
+// A scheduled backup, the CRON expression and timezone are parsed when the
+// job is loaded. Each run happens on its own thread, so a long archive
+// doesn't hold up the other jobs.
+struct Job {
+    name: String,
+    args: ScheduleArgs,
+    schedule: Schedule,
+    timezone: Tz,
+    running: Arc<AtomicBool>,
 }
 
-pub fn schedule_borg(schedule_args: &ScheduleArgs) {
-    if schedule_args.daemonize {
-        match daemonize_schedule() {
-            Ok(()) => util::log_print("Running daemon", util::LogLevel::Info),
-            Err(e) => util::log_print(&format!("Error: {}", e), util::LogLevel::Error),
+impl Job {
+    fn new(args: ScheduleArgs) -> Result<Job, ScheduleError> {
+        Ok(Job {
+            name: args.job_name(),
+            schedule: args.generate_expression()?,
+            timezone: args.generate_timezone()?,
+            running: Arc::new(AtomicBool::new(false)),
+            args,
+        })
+    }
+
+    // Returns the first time the job is due after `time`.
+    fn next_after(&self, time: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule
+            .after(&time.with_timezone(&self.timezone))
+            .next()
+            .map(|n| n.with_timezone(&Utc))
+    }
+
+    // Returns the times the job was due after `since`, up to and
+    // including `until`.
+    fn due_between(&self, since: &DateTime<Utc>, until: &DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        self.schedule
+            .after(&since.with_timezone(&self.timezone))
+            .map(|n| n.with_timezone(&Utc))
+            .take_while(|n| n <= until)
+            .collect()
+    }
+
+    // Returns the arguments for making up a run missed at `scheduled`.
+    // A fixed archive name gets the scheduled time appended, otherwise
+    // every replay after the first would find the archive already
+    // exists. Names using borg's `{now}` or `{utcnow}` placeholders are
+    // already unique, as are the epoch names used when none is given.
+    fn replay_args(&self, scheduled: &DateTime<Utc>) -> ScheduleArgs {
+        let archive = self.args.archive().map(|n| {
+            if n.contains("{now") || n.contains("{utcnow") {
+                n
+            } else {
+                let time = scheduled.with_timezone(&self.timezone);
+                format!("{}-{}", n, time.format("%Y-%m-%dT%H:%M:%S"))
+            }
+        });
+        ScheduleArgs {
+            archive,
+            ..self.args.to_owned()
         }
     }
 
-    let schedule = schedule_args.generate_expression();
-    let timezone = schedule_args.generate_timezone();
+    // Makes up for the runs missed since the job's last successful run,
+    // as recorded in the run history, following the missed run policy.
+    // A job that has never succeeded has nothing to catch up on.
+    fn catch_up(&self, now: &DateTime<Utc>, shutdown: &Arc<AtomicBool>) -> Option<JoinHandle<()>> {
+        let records = match self
+            .args
+            .history_path()
+            .and_then(|n| history::read_records(&n))
+        {
+            Ok(n) => n,
+            Err(e) => {
+                util::log_print(
+                    &format!("[{}] Unable to check for missed runs: {}", self.name, e),
+                    util::LogLevel::Warn,
+                );
+                return None;
+            }
+        };
 
-    loop {
-        let now = Utc::now().with_timezone(&timezone);
-        if let Some(next) = schedule.upcoming(timezone).take(1).next() {
-            let until_next = next - now;
-            thread::sleep(until_next.to_std().unwrap());
-            if !verify_repo_location(&schedule_args.repository, &schedule_args.passphrase) {
-                match initialise_repository(schedule_args) {
-                    Ok(_) => {
-                        util::log_print("Repository successfully created", util::LogLevel::Info)
-                    }
-                    Err(e) => {
-                        util::log_print(&format!("Operation failed: {}", e), util::LogLevel::Error)
-                    }
+        let last_success = history::last_success(&records, &self.name)?;
+        let missed = self.due_between(&last_success, now);
+        let total = missed.len();
+        let replays = self.args.missed_runs().select(missed);
+        if replays.is_empty() {
+            return None;
+        }
+
+        util::log_print(
+            &format!(
+                "[{}] Catching up {} of {} missed run(s)",
+                self.name,
+                replays.len(),
+                total
+            ),
+            util::LogLevel::Info,
+        );
+        self.start(
+            replays.iter().map(|n| self.replay_args(n)).collect(),
+            shutdown,
+        )
+    }
+
+    // Starts consecutive runs of the job in the background, one for each
+    // of `runs`, unless the previous run is still going in which case
+    // they are skipped. Runs that haven't started yet are dropped once
+    // `shutdown` is set.
+    fn start(&self, runs: Vec<ScheduleArgs>, shutdown: &Arc<AtomicBool>) -> Option<JoinHandle<()>> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            util::log_print(
+                &format!("[{}] Previous run still in progress, skipping", self.name),
+                util::LogLevel::Warn,
+            );
+            return None;
+        }
+
+        let name = self.name.to_owned();
+        let running = Arc::clone(&self.running);
+        let shutdown = Arc::clone(shutdown);
+        Some(thread::spawn(move || {
+            for args in runs {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
                 }
+                let start = Utc::now();
+                let result = run_job(&name, &args);
+                let record = RunRecord::new(&name, &args.repository(), start, Utc::now(), result);
+                record_run(&record, &args);
             }
+            running.store(false, Ordering::SeqCst);
+        }))
+    }
+}
+
+// Appends a finished run to the history file, a run that can't be
+// recorded is reported but doesn't stop the scheduler.
+fn record_run(record: &RunRecord, schedule_args: &ScheduleArgs) {
+    let result = schedule_args
+        .history_path()
+        .and_then(|path| history::append_record(&path, record));
+    if let Err(e) = result {
+        util::log_print(
+            &format!("[{}] Failed to record run: {}", record.job, e),
+            util::LogLevel::Error,
+        );
+    }
+}
+
+// Creates an archive for a job, initialising the repository first if it
+// doesn't exist yet, and applies the retention policy afterwards. A remote
+// repository is checked first, so an unreachable host isn't mistaken for
+// a missing repository.
+//
+// The created archive's name and stats are returned to be recorded in
+// the run history. A partial backup still counts as a successful run,
+// with borg's warnings logged.
+fn run_job(name: &str, schedule_args: &ScheduleArgs) -> Result<(String, ArchiveStats), RunError> {
+    if let Err(e) = preflight_check(schedule_args) {
+        util::log_print(
+            &format!("[{}] Pre-flight check failed: {}", name, e),
+            util::LogLevel::Error,
+        );
+        return Err(RunError::new("preflight_failed", &e));
+    }
 
-            match create_archive(schedule_args) {
-                Ok(n) => {
-                    if schedule_args.verbose {
-                        display_create_info(n)
-                    } else {
-                        util::log_print("Archive created!", util::LogLevel::Info)
+    if !verify_repo_location(schedule_args) {
+        match initialise_repository(schedule_args) {
+            Ok(encryption) => util::log_print(
+                &format!(
+                    "[{}] Repository successfully created with {} encryption",
+                    name, encryption
+                ),
+                util::LogLevel::Info,
+            ),
+            Err(e) => {
+                util::log_print(
+                    &format!("[{}] Repository initialisation failed: {}", name, e),
+                    util::LogLevel::Error,
+                );
+                return Err(RunError::new("repository_init_failed", &e));
+            }
+        }
+    }
+
+    let created = match create_archive(schedule_args) {
+        Ok(n) => n,
+        Err(ArchiveError::PartialBackup(n, warnings)) => {
+            warnings.iter().for_each(|warning| {
+                util::log_print(&format!("[{}] {}", name, warning), util::LogLevel::Warn)
+            });
+            *n
+        }
+        Err(err) => {
+            util::log_print(
+                &format!("[{}] Archive creation failed: {}", name, err),
+                util::LogLevel::Error,
+            );
+            return Err(RunError::new(err.kind(), &err));
+        }
+    };
+
+    let archive = (created.archive.name.to_owned(), created.archive.stats);
+    if schedule_args.verbose {
+        display_create_info(created)
+    } else {
+        util::log_print(
+            &format!("[{}] Archive created!", name),
+            util::LogLevel::Info,
+        )
+    }
+    apply_retention(name, schedule_args);
+    Ok(archive)
+}
+
+// Forwards SIGTERM, SIGINT and SIGHUP to the scheduler loop. The sender
+// is returned alongside the receiver so the channel stays open even if
+// the handlers couldn't be registered.
+fn listen_for_signals() -> (Sender<i32>, Receiver<i32>) {
+    let (sender, receiver) = mpsc::channel();
+    match Signals::new([SIGTERM, SIGINT, SIGHUP]) {
+        Ok(mut signals) => {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for signal in signals.forever() {
+                    if sender.send(signal).is_err() {
+                        break;
                     }
                 }
-                Err(err) => {
-                    if schedule_args.verbose {
-                        parse_archive_error(err);
-                    }
+            });
+        }
+        Err(e) => util::log_print(
+            &format!("Failed to register signal handlers: {}", e),
+            util::LogLevel::Warn,
+        ),
+    }
+    (sender, receiver)
+}
+
+// Builds the jobs from reloaded arguments. A job that keeps its name keeps
+// its running state, so a run still in progress isn't started twice.
+fn reload_jobs(jobs: &[Job], reloaded: Vec<ScheduleArgs>) -> Result<Vec<Job>, ScheduleError> {
+    reloaded
+        .into_iter()
+        .map(|args| {
+            let mut job = Job::new(args)?;
+            if let Some(n) = jobs.iter().find(|n| n.name == job.name) {
+                job.running = Arc::clone(&n.running);
+            }
+            Ok(job)
+        })
+        .collect()
+}
+
+// The upcoming runs of a job, printed by `schedule --preview`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SchedulePreview {
+    pub job: String,
+    pub expression: String,
+    pub timezone: String,
+    pub runs: Vec<DateTime<Tz>>,
+}
+
+// Returns the next `count` times each job is due in its timezone,
+// so expressions can be checked before they are scheduled.
+pub fn preview_schedule(
+    jobs: &[ScheduleArgs],
+    count: usize,
+) -> Result<Vec<SchedulePreview>, ScheduleError> {
+    jobs.iter()
+        .map(|args| {
+            let schedule = args.generate_expression()?;
+            let timezone = args.generate_timezone()?;
+            Ok(SchedulePreview {
+                job: args.job_name(),
+                expression: args.expression(),
+                timezone: args.timezone(),
+                runs: schedule.upcoming(timezone).take(count).collect(),
+            })
+        })
+        .collect()
+}
+
+pub fn display_schedule_preview(previews: Vec<SchedulePreview>) {
+    previews.iter().for_each(|preview| {
+        util::log_print(
+            &format!(
+                "[{}] {} ({})",
+                preview.job, preview.expression, preview.timezone
+            ),
+            util::LogLevel::Info,
+        );
+        if preview.runs.is_empty() {
+            util::log_print("No upcoming runs", util::LogLevel::Warn);
+        }
+        preview.runs.iter().for_each(|run| {
+            util::log_print(
+                &run.format("%a %Y-%m-%d %H:%M:%S %Z").to_string(),
+                util::LogLevel::Info,
+            )
+        });
+    });
+}
+
+// Runs every job on its own schedule. The daemon sleeps until the next
+// job is due, then starts all jobs due at that time.
+//
+// Runs missed while the scheduler was down are caught up on start, and
+// those missed while the machine was asleep are caught up on waking,
+// following each job's missed run policy.
+//
+// SIGHUP reloads the jobs with `reload`, keeping the current jobs if that
+// fails. SIGTERM and SIGINT stop new runs from starting and wait for the
+// running ones to finish before exiting, so no borg lock is left behind.
+//
+// Every job's CRON expression and timezone are checked before daemonizing,
+// so a mistake is reported rather than stopping the daemon later.
+pub fn schedule_borg(
+    jobs: Vec<ScheduleArgs>,
+    reload: impl Fn() -> Result<Vec<ScheduleArgs>, ConfigError>,
+) -> Result<(), ScheduleError> {
+    let daemon_args = jobs.iter().find(|n| n.daemonize).cloned();
+    let mut jobs: Vec<Job> = jobs
+        .into_iter()
+        .map(Job::new)
+        .collect::<Result<Vec<Job>, ScheduleError>>()?;
+
+    let lock_args = daemon_args.as_ref().or(jobs.first().map(|n| &n.args));
+    let mut pid_lock = match lock_args {
+        Some(n) => Some(lock_pid_file(n).map_err(ScheduleError::ScheduleDaemonError)?),
+        None => None,
+    };
+    if let (Some(n), Some(pid_lock)) = (&daemon_args, pid_lock.as_mut()) {
+        daemonize_schedule(n, pid_lock).map_err(ScheduleError::ScheduleDaemonError)?;
+        util::log_print("Running daemon", util::LogLevel::Info);
+    }
+
+    let (_sender, signals) = listen_for_signals();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let mut workers: Vec<JoinHandle<()>> = Vec::new();
+
+    let now = Utc::now();
+    workers.extend(jobs.iter().filter_map(|job| job.catch_up(&now, &shutdown)));
+    let mut next_runs: Vec<Option<DateTime<Utc>>> =
+        jobs.iter().map(|job| job.next_after(&now)).collect();
+
+    loop {
+        let next = next_runs.iter().flatten().min().copied();
+        let signal = match next {
+            Some(n) => signals
+                .recv_timeout((n - Utc::now()).to_std().unwrap_or_default())
+                .ok(),
+            None => {
+                util::log_print("No upcoming jobs to run", util::LogLevel::Warn);
+                signals.recv().ok()
+            }
+        };
+
+        match signal {
+            Some(SIGHUP) => {
+                match reload() {
+                    Ok(n) => match reload_jobs(&jobs, n) {
+                        Ok(n) => {
+                            jobs = n;
+                            let now = Utc::now();
+                            next_runs = jobs.iter().map(|job| job.next_after(&now)).collect();
+                            util::log_print("Configuration reloaded", util::LogLevel::Info);
+                        }
+                        Err(e) => util::log_print(
+                            &format!("Failed to reload configuration: {}", e),
+                            util::LogLevel::Error,
+                        ),
+                    },
+                    Err(e) => util::log_print(
+                        &format!("Failed to reload configuration: {}", e),
+                        util::LogLevel::Error,
+                    ),
                 }
+                continue;
             }
+            Some(_) => break,
+            None => (),
         }
+
+        let Some(next) = next else { continue };
+        for (job, next_run) in jobs.iter().zip(next_runs.iter_mut()) {
+            if *next_run == Some(next) {
+                let now = Utc::now().max(next);
+                let missed = job.args.missed_runs().select(job.due_between(&next, &now));
+                let mut runs = vec![job.args.to_owned()];
+                runs.extend(missed.iter().map(|n| job.replay_args(n)));
+                workers.extend(job.start(runs, &shutdown));
+                *next_run = job.next_after(&now);
+            }
+        }
+        workers.retain(|n| !n.is_finished());
+    }
+
+    shutdown.store(true, Ordering::SeqCst);
+    if workers.iter().any(|n| !n.is_finished()) {
+        util::log_print(
+            "Shutting down, waiting for running jobs to finish",
+            util::LogLevel::Info,
+        );
     }
+    workers.into_iter().for_each(|n| {
+        let _ = n.join();
+    });
+
+    drop(pid_lock);
+    util::log_print("Scheduler stopped", util::LogLevel::Info);
+    Ok(())
 }
 
 #[cfg(test)]
@@ -154,14 +1043,27 @@ mod test {
         ScheduleArgs {
             daemonize: false,
             verbose: false,
-            expression: "0 0 * * 1".to_owned(),
-            timezone: "Etc/UTC".to_owned(),
-            repository: "repository".to_owned(),
-            passphrase: "passphrase".to_owned(),
+            pid_file: DEFAULT_PID_FILE.to_owned(),
+            log_directory: DEFAULT_LOG_DIRECTORY.to_owned(),
+            working_directory: DEFAULT_WORKING_DIRECTORY.to_owned(),
+            expression: Some("0 0 * * 1".to_owned()),
+            timezone: Some("Etc/UTC".to_owned()),
+            repository: Some("repository".to_owned()),
+            passphrase: Some("passphrase".to_owned()),
             archive: None,
             paths: vec![],
             include_patterns: None,
             exclude_patterns: None,
+            retention: RetentionArgs::default(),
+            compact: false,
+            compact_threshold: None,
+            profile: None,
+            passphrase_source: PassphraseArgs::default(),
+            remote: RemoteArgs::default(),
+            jobs: vec![],
+            history_file: None,
+            missed_runs: MissedRunPolicy::Skip,
+            preview: None,
         }
     }
 
@@ -174,7 +1076,7 @@ mod test {
     #[test]
     fn test_get_passphrase() {
         let list_args = setup_schedule_args();
-        assert_eq!(list_args.passphrase(), "passphrase".to_owned());
+        assert_eq!(list_args.passphrase(), Some("passphrase".to_owned()));
     }
 
     #[test]
@@ -246,4 +1148,383 @@ mod test {
         list_args1.exclude_patterns = None;
         assert_eq!(list_args1.exclude_patterns(), None);
     }
+
+    #[test]
+    fn test_get_retention() {
+        let mut schedule_args = setup_schedule_args();
+        assert!(!schedule_args.retention().has_rules());
+
+        schedule_args.retention.keep_daily = std::num::NonZeroU16::new(7);
+        assert!(schedule_args.retention().has_rules());
+        assert!(!schedule_args.dry_run());
+    }
+
+    #[test]
+    fn test_get_expression_default() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = None;
+        schedule_args.timezone = None;
+        assert_eq!(schedule_args.expression(), DEFAULT_EXPRESSION);
+        assert_eq!(schedule_args.timezone(), DEFAULT_TIMEZONE);
+    }
+
+    #[test]
+    fn test_apply_profile() {
+        let profile = Profile {
+            repository: Some("profile_repository".to_owned()),
+            paths: vec!["/home".to_owned()],
+            expression: Some("0 0 2 * * *".to_owned()),
+            timezone: Some("Europe/London".to_owned()),
+            ..Default::default()
+        };
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.repository = None;
+        schedule_args.expression = None;
+        schedule_args.verbose = true;
+
+        let result = schedule_args.apply_profile("nightly", &profile).unwrap();
+        assert_eq!(result.repository(), "profile_repository");
+        assert_eq!(result.passphrase(), Some("passphrase".to_owned()));
+        assert_eq!(result.paths(), vec!["/home"]);
+        assert_eq!(result.expression(), "0 0 2 * * *");
+        assert_eq!(result.timezone(), "Etc/UTC");
+        assert!(result.verbose);
+    }
+
+    #[test]
+    fn test_profile_options() {
+        let mut schedule_args = setup_schedule_args();
+        assert_eq!(
+            schedule_args.profile_options(),
+            ["--repository", "--passphrase", "--expression", "--timezone"]
+        );
+
+        schedule_args.repository = None;
+        schedule_args.passphrase = None;
+        schedule_args.expression = None;
+        schedule_args.timezone = None;
+        schedule_args.compact = true;
+        assert!(schedule_args.profile_options().is_empty());
+    }
+
+    #[test]
+    fn test_get_job_name() {
+        let mut schedule_args = setup_schedule_args();
+        assert_eq!(schedule_args.job_name(), DEFAULT_JOB_NAME);
+
+        schedule_args.profile = Some("nightly".to_owned());
+        assert_eq!(schedule_args.job_name(), "nightly");
+    }
+
+    #[test]
+    fn test_job_next_after() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = Some("0 30 2 * * *".to_owned());
+        schedule_args.timezone = Some("Europe/London".to_owned());
+        let job = Job::new(schedule_args).unwrap();
+
+        let time = DateTime::parse_from_rfc3339("2025-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let next = job.next_after(&time).unwrap();
+        assert_eq!(next.to_rfc3339(), "2025-01-02T02:30:00+00:00");
+
+        let time = DateTime::parse_from_rfc3339("2025-07-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let next = job.next_after(&time).unwrap();
+        assert_eq!(next.to_rfc3339(), "2025-07-02T01:30:00+00:00");
+    }
+
+    #[test]
+    fn test_job_start_skips_running() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = Some("0 0 0 * * Mon".to_owned());
+        let job = Job::new(schedule_args).unwrap();
+        job.running.store(true, Ordering::SeqCst);
+        let runs = vec![job.args.to_owned()];
+        assert!(job.start(runs, &Arc::new(AtomicBool::new(false))).is_none());
+        assert!(job.running.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_missed_run_policy_runs() {
+        assert_eq!(MissedRunPolicy::Skip.runs(3), 0);
+        assert_eq!(MissedRunPolicy::Once.runs(3), 1);
+        assert_eq!(MissedRunPolicy::Once.runs(0), 0);
+        assert_eq!(MissedRunPolicy::All.runs(3), 3);
+    }
+
+    #[test]
+    fn test_missed_run_policy_select() {
+        let missed: Vec<DateTime<Utc>> = ["2025-01-01T02:00:00Z", "2025-01-02T02:00:00Z"]
+            .iter()
+            .map(|n| DateTime::parse_from_rfc3339(n).unwrap().with_timezone(&Utc))
+            .collect();
+        assert!(MissedRunPolicy::Skip.select(missed.clone()).is_empty());
+        assert_eq!(MissedRunPolicy::Once.select(missed.clone()), missed[1..]);
+        assert_eq!(MissedRunPolicy::All.select(missed.clone()), missed);
+        assert!(MissedRunPolicy::All.select(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_job_due_between() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = Some("0 0 2 * * *".to_owned());
+        let job = Job::new(schedule_args).unwrap();
+
+        let since = DateTime::parse_from_rfc3339("2025-01-01T02:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let until = DateTime::parse_from_rfc3339("2025-01-04T02:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let due = job.due_between(&since, &until);
+        assert_eq!(due.len(), 3);
+        assert_eq!(due[0].to_rfc3339(), "2025-01-02T02:00:00+00:00");
+        assert_eq!(due[2], until);
+        assert!(job.due_between(&since, &since).is_empty());
+    }
+
+    #[test]
+    fn test_job_replay_args() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = Some("0 0 2 * * *".to_owned());
+        schedule_args.timezone = Some("Europe/London".to_owned());
+        schedule_args.archive = Some("nightly".to_owned());
+        let job = Job::new(schedule_args).unwrap();
+
+        let first = DateTime::parse_from_rfc3339("2025-07-01T01:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let second = DateTime::parse_from_rfc3339("2025-07-02T01:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            job.replay_args(&first).archive(),
+            Some("nightly-2025-07-01T02:00:00".to_owned())
+        );
+        assert_eq!(
+            job.replay_args(&second).archive(),
+            Some("nightly-2025-07-02T02:00:00".to_owned())
+        );
+
+        let mut schedule_args = job.args.to_owned();
+        schedule_args.archive = Some("nightly-{now}".to_owned());
+        let job = Job::new(schedule_args).unwrap();
+        assert_eq!(
+            job.replay_args(&first).archive(),
+            Some("nightly-{now}".to_owned())
+        );
+
+        let mut schedule_args = job.args.to_owned();
+        schedule_args.archive = None;
+        let job = Job::new(schedule_args).unwrap();
+        assert_eq!(job.replay_args(&first).archive(), None);
+    }
+
+    #[test]
+    fn test_job_start_after_shutdown() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = Some("0 0 0 * * Mon".to_owned());
+        let job = Job::new(schedule_args).unwrap();
+
+        let runs = vec![job.args.to_owned()];
+        let worker = job.start(runs, &Arc::new(AtomicBool::new(true))).unwrap();
+        worker.join().unwrap();
+        assert!(!job.running.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_reload_jobs() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = Some("0 0 0 * * Mon".to_owned());
+        schedule_args.profile = Some("nightly".to_owned());
+        let job = Job::new(schedule_args.clone()).unwrap();
+        job.running.store(true, Ordering::SeqCst);
+
+        let mut reloaded_args = schedule_args.clone();
+        reloaded_args.profile = Some("hourly".to_owned());
+        let result = reload_jobs(&[job], vec![schedule_args, reloaded_args]).unwrap();
+        assert_eq!(result.len(), 2);
+        assert!(result[0].running.load(Ordering::SeqCst));
+        assert!(!result[1].running.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_check_single_instance() {
+        let pid_dir = tempfile::tempdir().unwrap();
+        let pid_file = pid_dir.path().join("rusty_borg.pid");
+        let pid_lock = check_single_instance(&pid_file).unwrap();
+        assert_eq!(
+            fs::read_to_string(&pid_file).unwrap(),
+            format!("{}\n", process::id())
+        );
+        drop(pid_lock);
+        assert!(!pid_file.exists());
+
+        fs::write(&pid_file, "1234\n").unwrap();
+        assert!(check_single_instance(&pid_file).is_ok());
+
+        fs::write(&pid_file, "1234\n").unwrap();
+        let lock = File::open(&pid_file).unwrap();
+        lock.lock().unwrap();
+        match check_single_instance(&pid_file) {
+            Err(DaemonError::AlreadyRunning(_, pid)) => assert_eq!(pid, Some("1234".to_owned())),
+            _ => panic!("Expected an already running error"),
+        }
+        assert!(pid_file.exists());
+    }
+
+    #[test]
+    fn test_check_single_instance_foreground() {
+        let pid_dir = tempfile::tempdir().unwrap();
+        let pid_file = pid_dir.path().join("rusty_borg.pid");
+        let pid_lock = check_single_instance(&pid_file).unwrap();
+        assert!(matches!(
+            check_single_instance(&pid_file),
+            Err(DaemonError::AlreadyRunning(_, _))
+        ));
+        drop(pid_lock);
+        assert!(check_single_instance(&pid_file).is_ok());
+    }
+
+    #[test]
+    fn test_absolute_history_file() {
+        let working_dir = tempfile::tempdir().unwrap();
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.daemonize = true;
+        schedule_args.working_directory = working_dir.path().display().to_string();
+        schedule_args.history_file = Some("history.jsonl".to_owned());
+
+        let schedule_args = schedule_args.absolute_history_file().unwrap();
+        let history_path = schedule_args.history_path().unwrap();
+        let expected = std::env::current_dir().unwrap().join("history.jsonl");
+        assert_eq!(history_path, expected);
+
+        // The daemon looks paths up from its working directory.
+        let daemon_path = Path::new(&schedule_args.working_directory).join(&history_path);
+        assert_eq!(daemon_path, expected);
+
+        let schedule_args = setup_schedule_args().absolute_history_file().unwrap();
+        assert_eq!(schedule_args.history_file, None);
+    }
+
+    #[test]
+    fn test_open_log_file_appends() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_directory = log_dir.path().join("logs");
+        for line in ["first\n", "second\n"] {
+            let mut file = open_log_file(&log_directory, STDOUT_LOG_FILE).unwrap();
+            std::io::Write::write_all(&mut file, line.as_bytes()).unwrap();
+        }
+        let contents = fs::read_to_string(log_directory.join(STDOUT_LOG_FILE)).unwrap();
+        assert_eq!(contents, "first\nsecond\n");
+    }
+
+    #[test]
+    fn test_generate_expression_fail() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = Some("0 0 25 * * *".to_owned());
+        assert!(matches!(
+            schedule_args.generate_expression(),
+            Err(ScheduleError::InvalidExpression(_, _))
+        ));
+        assert!(schedule_args.validate().is_err());
+        assert!(Job::new(schedule_args).is_err());
+    }
+
+    #[test]
+    fn test_generate_timezone_fail() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = Some("0 0 0 * * Mon".to_owned());
+        schedule_args.timezone = Some("Europe/Nowhere".to_owned());
+        assert!(matches!(
+            schedule_args.generate_timezone(),
+            Err(ScheduleError::InvalidTimezone(_))
+        ));
+        assert!(schedule_args.validate().is_err());
+    }
+
+    #[test]
+    fn test_preview_schedule() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = Some("0 30 2 * * *".to_owned());
+        schedule_args.timezone = Some("Europe/London".to_owned());
+
+        let result = preview_schedule(&[schedule_args], 3).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].job, DEFAULT_JOB_NAME);
+        assert_eq!(result[0].runs.len(), 3);
+        assert!(result[0]
+            .runs
+            .iter()
+            .all(|n| n.format("%H:%M:%S").to_string() == "02:30:00"));
+    }
+
+    #[test]
+    fn test_normalise_expression() {
+        assert_eq!(normalise_expression("0 0 * * 1").unwrap(), "0 0 0 * * Mon");
+        assert_eq!(
+            normalise_expression("30 2 * * 0,6").unwrap(),
+            "0 30 2 * * Sun,Sat"
+        );
+        assert_eq!(
+            normalise_expression("*/15 * * * 1-5").unwrap(),
+            "0 */15 * * * Mon-Fri"
+        );
+        assert_eq!(
+            normalise_expression("0 0 * * 5-7").unwrap(),
+            "0 0 0 * * Fri-Sat,Sun"
+        );
+        assert_eq!(
+            normalise_expression("0 0 * * */2").unwrap(),
+            "0 0 0 * * */2"
+        );
+        assert_eq!(
+            normalise_expression(" 0 30 2 * * Mon ").unwrap(),
+            "0 30 2 * * Mon"
+        );
+        assert_eq!(normalise_expression("@daily").unwrap(), "0 0 0 * * *");
+        assert_eq!(normalise_expression("@Weekly").unwrap(), "0 0 0 * * Sun");
+        assert_eq!(normalise_expression("@hourly").unwrap(), "0 0 * * * *");
+
+        assert!(normalise_expression("@sometimes").is_err());
+        assert!(normalise_expression("0 0 * * 8").is_err());
+        assert!(normalise_expression("0 0 *").is_err());
+        assert!(normalise_expression("0 3 1 * 1").is_err());
+        assert!(normalise_expression("0 3 1-7 * Mon").is_err());
+        assert_eq!(
+            normalise_expression("0 3 */2 * 1").unwrap(),
+            "0 0 3 */2 * Mon"
+        );
+    }
+
+    #[test]
+    fn test_normalise_expression_day_of_week_numbers() {
+        // Crontab numbers Monday 1, the `cron` crate numbers it 2
+        let crontab = Schedule::from_str(&normalise_expression("0 3 * * 1").unwrap()).unwrap();
+        let cron = Schedule::from_str(&normalise_expression("0 0 3 * * 2").unwrap()).unwrap();
+        let crontab_runs: Vec<DateTime<Utc>> = crontab.upcoming(Utc).take(3).collect();
+        let cron_runs: Vec<DateTime<Utc>> = cron.upcoming(Utc).take(3).collect();
+        assert_eq!(crontab_runs, cron_runs);
+        assert!(crontab_runs
+            .iter()
+            .all(|n| n.format("%a %H:%M").to_string() == "Mon 03:00"));
+    }
+
+    #[test]
+    fn test_generate_expression_crontab() {
+        let mut schedule_args = setup_schedule_args();
+        schedule_args.expression = None;
+        schedule_args.timezone = Some("Etc/UTC".to_owned());
+        let schedule = schedule_args.generate_expression().unwrap();
+        assert!(schedule
+            .upcoming(Utc)
+            .take(3)
+            .all(|n| n.format("%a %H:%M:%S").to_string() == "Mon 00:00:00"));
+
+        schedule_args.expression = Some("@daily".to_owned());
+        assert!(schedule_args.generate_expression().is_ok());
+    }
 }
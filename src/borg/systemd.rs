@@ -0,0 +1,686 @@
+use super::errors::SystemdError;
+use super::passphrase::{PassphraseArgs, PassphraseTrait};
+use super::schedule::{MissedRunPolicy, ScheduleArgs};
+use super::{BorgTrait, CompactTrait, CreateTrait, PruneTrait};
+use crate::util;
+use clap::Parser;
+use std::env;
+use std::fs;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+const DEFAULT_UNIT_NAME: &str = "rusty-borg";
+const DEFAULT_CREDENTIAL_DIRECTORY: &str = "/etc/rusty_borg";
+const CREDENTIAL_NAME: &str = "passphrase";
+// Expanded by systemd when the service starts, so it is left unescaped.
+const CREDENTIAL_PATH: &str = "${CREDENTIALS_DIRECTORY}/passphrase";
+
+const DAYS_OF_WEEK: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+// Struct for managing the necessary arguments for generating systemd units,
+// taking the same job options as `schedule` so a job can be moved between
+// the two. The options of the scheduler process itself are rejected.
+#[derive(Debug, Clone, Parser)]
+pub struct SystemdArgs {
+    #[command(flatten)]
+    schedule: ScheduleArgs,
+    #[arg(long, default_value = DEFAULT_UNIT_NAME)]
+    unit_name: String,
+    #[arg(long)]
+    install_directory: Option<String>,
+    #[arg(long, default_value = DEFAULT_CREDENTIAL_DIRECTORY)]
+    credential_directory: String,
+}
+
+impl PassphraseTrait for SystemdArgs {
+    fn passphrase_source(&self) -> &PassphraseArgs {
+        self.schedule.passphrase_source()
+    }
+
+    fn with_passphrase(self, passphrase: String) -> Self {
+        SystemdArgs {
+            schedule: self.schedule.with_passphrase(passphrase),
+            ..self
+        }
+    }
+}
+
+impl SystemdArgs {
+    pub fn schedule(&self) -> ScheduleArgs {
+        self.schedule.to_owned()
+    }
+}
+
+// A generated `.service` and `.timer` pair for one scheduled job, along
+// with the credential file the service reads the passphrase from.
+#[derive(Debug, Clone)]
+pub struct SystemdUnits {
+    pub name: String,
+    pub service: String,
+    pub timer: String,
+    pub credential_file: String,
+    pub passphrase: Option<String>,
+}
+
+// The fields of a CRON expression as accepted by the `cron` crate, with the
+// range of values each accepts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CronField {
+    Second,
+    Minute,
+    Hour,
+    DayOfMonth,
+    Month,
+    DayOfWeek,
+    Year,
+}
+
+impl CronField {
+    fn range(self) -> (u32, u32) {
+        match self {
+            CronField::Second | CronField::Minute => (0, 59),
+            CronField::Hour => (0, 23),
+            CronField::DayOfMonth => (1, 31),
+            CronField::Month => (1, 12),
+            CronField::DayOfWeek => (1, 7),
+            CronField::Year => (1970, 2100),
+        }
+    }
+
+    // Parses a single value, month and day names are accepted
+    // case-insensitively by their first three letters.
+    fn parse_value(self, value: &str) -> Option<u32> {
+        if let Ok(n) = value.parse::<u32>() {
+            let (min, max) = self.range();
+            return (min..=max).contains(&n).then_some(n);
+        }
+        let name = value.get(..3)?.to_lowercase();
+        match self {
+            CronField::Month => MONTHS.iter().position(|n| *n == name),
+            CronField::DayOfWeek => DAYS_OF_WEEK.iter().position(|n| n.to_lowercase() == name),
+            _ => None,
+        }
+        .map(|n| n as u32 + 1)
+    }
+
+    // Formats a value for systemd, which names days of the week
+    // (`1` is Sunday in the `cron` crate) and numbers everything else.
+    fn format_value(self, value: u32) -> String {
+        match self {
+            CronField::DayOfWeek => DAYS_OF_WEEK[(value - 1) as usize].to_owned(),
+            CronField::Second | CronField::Minute | CronField::Hour => format!("{:02}", value),
+            _ => value.to_string(),
+        }
+    }
+}
+
+// Translates one part of a comma-separated CRON field. Steps over a range
+// are expanded into a list, as are any steps over the days of the week,
+// since systemd only repeats from a starting value.
+fn translate_part(field: CronField, part: &str) -> Option<String> {
+    let (min, max) = field.range();
+    let (range, step) = match part.split_once('/') {
+        Some((range, step)) => (range, Some(step.parse::<u32>().ok().filter(|n| *n > 0)?)),
+        None => (part, None),
+    };
+
+    let (start, end) = match range {
+        "*" | "?" => (None, None),
+        _ => match range.split_once('-') {
+            Some((start, end)) => (
+                Some(field.parse_value(start)?),
+                Some(field.parse_value(end)?),
+            ),
+            None => (Some(field.parse_value(range)?), None),
+        },
+    };
+
+    match (start, end, step) {
+        (None, _, None) => Some("*".to_owned()),
+        (Some(start), None, None) => Some(field.format_value(start)),
+        (Some(start), Some(end), None) => Some(format!(
+            "{}..{}",
+            field.format_value(start),
+            field.format_value(end)
+        )),
+        (start, end, Some(step)) => {
+            let start = start.unwrap_or(min);
+            let end = end.unwrap_or(max);
+            if field == CronField::DayOfWeek || end != max {
+                Some(
+                    (start..=end)
+                        .step_by(step as usize)
+                        .map(|n| field.format_value(n))
+                        .collect::<Vec<String>>()
+                        .join(","),
+                )
+            } else {
+                Some(format!("{}/{}", field.format_value(start), step))
+            }
+        }
+    }
+}
+
+fn translate_field(field: CronField, value: &str) -> Result<String, SystemdError> {
+    let parts: Option<Vec<String>> = value
+        .split(',')
+        .map(|part| translate_part(field, part))
+        .collect();
+    parts
+        .map(|n| n.join(","))
+        .ok_or_else(|| SystemdError::UnsupportedExpression(value.to_owned()))
+}
+
+// Translates a CRON expression and timezone into a systemd `OnCalendar=`
+// value, e.g. `0 30 2 * * Mon-Fri` in `Europe/London` becomes
+// `Mon..Fri *-*-* 02:30:00 Europe/London`.
+//
+// The expression is expected to have been validated by the `cron` crate.
+fn to_on_calendar(expression: &str, timezone: &str) -> Result<String, SystemdError> {
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    let year = match fields.len() {
+        6 => "*",
+        7 => fields[6],
+        _ => return Err(SystemdError::UnsupportedExpression(expression.to_owned())),
+    };
+
+    let second = translate_field(CronField::Second, fields[0])?;
+    let minute = translate_field(CronField::Minute, fields[1])?;
+    let hour = translate_field(CronField::Hour, fields[2])?;
+    let day = translate_field(CronField::DayOfMonth, fields[3])?;
+    let month = translate_field(CronField::Month, fields[4])?;
+    let day_of_week = translate_field(CronField::DayOfWeek, fields[5])?;
+    let year = translate_field(CronField::Year, year)?;
+
+    let calendar = format!(
+        "{}-{}-{} {}:{}:{} {}",
+        year, month, day, hour, minute, second, timezone
+    );
+    match day_of_week.as_str() {
+        "*" => Ok(calendar),
+        _ => Ok(format!("{} {}", day_of_week, calendar)),
+    }
+}
+
+// Quotes an argument for an `ExecStart=` line when needed, escaping the
+// characters systemd would otherwise expand.
+fn quote_exec_arg(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    let needs_quotes = escaped.is_empty()
+        || escaped
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';'));
+    match needs_quotes {
+        true => format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => escaped,
+    }
+}
+
+fn exec_start(executable: &str, args: Vec<String>) -> String {
+    let args: Vec<String> = args
+        .iter()
+        .map(|n| match n == CREDENTIAL_PATH {
+            true => n.to_owned(),
+            false => quote_exec_arg(n),
+        })
+        .collect();
+    format!(
+        "ExecStart={} {}",
+        quote_exec_arg(executable),
+        args.join(" ")
+    )
+}
+
+// Builds the commands the service runs for a job: `create`, followed by
+// `prune` and `compact` when the job applies a retention policy.
+fn exec_start_lines(
+    executable: &str,
+    schedule_args: &ScheduleArgs,
+    passphrase_args: &[String],
+) -> Vec<String> {
+    let repository = schedule_args.repository();
+    let remote_args = schedule_args.remote().to_args();
+    let mut create_args = vec!["create".to_owned(), repository.to_owned()];
+    create_args.extend(passphrase_args.iter().cloned());
+    create_args.extend(remote_args.iter().cloned());
+    if let Some(archive) = schedule_args.archive() {
+        create_args.extend(["--archive".to_owned(), archive]);
+    }
+    let pattern_options = [
+        ("--paths", Some(schedule_args.paths())),
+        ("--include-patterns", schedule_args.include_patterns()),
+        ("--exclude-patterns", schedule_args.exclude_patterns()),
+    ];
+    for (option, values) in pattern_options {
+        if let Some(values) = values.filter(|n| !n.is_empty()) {
+            create_args.push(option.to_owned());
+            create_args.extend(values);
+        }
+    }
+    let mut lines = vec![exec_start(executable, create_args)];
+
+    let retention = schedule_args.retention();
+    if retention.has_rules() {
+        let mut prune_args = vec!["prune".to_owned(), repository.to_owned()];
+        prune_args.extend(passphrase_args.iter().cloned());
+        prune_args.extend(remote_args.iter().cloned());
+        prune_args.extend(retention.to_args());
+        lines.push(exec_start(executable, prune_args));
+    }
+
+    if schedule_args.compact() {
+        let mut compact_args = vec!["compact".to_owned(), repository];
+        if let Some(n) = schedule_args.threshold() {
+            compact_args.push(format!("--threshold={}", n));
+        }
+        compact_args.extend(remote_args);
+        lines.push(exec_start(executable, compact_args));
+    }
+    lines
+}
+
+// Generates the units for one job. The passphrase is loaded by systemd as
+// a credential and read by `--passphrase-file`, so it never appears on
+// the `ExecStart=` lines.
+fn generate_units(
+    systemd_args: &SystemdArgs,
+    name: &str,
+    schedule_args: &ScheduleArgs,
+    executable: &str,
+) -> Result<SystemdUnits, SystemdError> {
+    schedule_args
+        .validate()
+        .map_err(SystemdError::InvalidSchedule)?;
+    let expression = schedule_args
+        .schedule_expression()
+        .map_err(SystemdError::InvalidSchedule)?;
+    let on_calendar = to_on_calendar(&expression, &schedule_args.timezone())?;
+    let credential_file = Path::new(&systemd_args.credential_directory)
+        .join(format!("{}.passphrase", name))
+        .display()
+        .to_string();
+    let passphrase = schedule_args.passphrase();
+
+    let mut service = vec![
+        "[Unit]".to_owned(),
+        format!(
+            "Description=RustyBorg backup ({})",
+            schedule_args.job_name()
+        ),
+        "Wants=network-online.target".to_owned(),
+        "After=network-online.target".to_owned(),
+        String::new(),
+        "[Service]".to_owned(),
+        "Type=oneshot".to_owned(),
+    ];
+    let passphrase_args = match passphrase {
+        Some(_) => {
+            service.push(format!(
+                "LoadCredential={}:{}",
+                CREDENTIAL_NAME, credential_file
+            ));
+            vec!["--passphrase-file".to_owned(), CREDENTIAL_PATH.to_owned()]
+        }
+        None => Vec::new(),
+    };
+    service.extend(exec_start_lines(
+        executable,
+        schedule_args,
+        &passphrase_args,
+    ));
+
+    // systemd only starts one run for any number of missed ones.
+    let persistent = schedule_args.missed_runs() != MissedRunPolicy::Skip;
+    let timer = [
+        "[Unit]".to_owned(),
+        format!(
+            "Description=Schedule RustyBorg backup ({})",
+            schedule_args.job_name()
+        ),
+        String::new(),
+        "[Timer]".to_owned(),
+        format!("OnCalendar={}", on_calendar),
+        format!("Persistent={}", persistent),
+        format!("Unit={}.service", name),
+        String::new(),
+        "[Install]".to_owned(),
+        "WantedBy=timers.target".to_owned(),
+    ];
+
+    Ok(SystemdUnits {
+        name: name.to_owned(),
+        service: service.join("\n") + "\n",
+        timer: timer.join("\n") + "\n",
+        credential_file,
+        passphrase,
+    })
+}
+
+// The entrypoint for the `systemd` module, generating a unit pair for
+// each job. When there are several jobs each pair is named after its job.
+//
+// Options that only configure the `schedule` process, such as
+// `--daemonize`, are rejected as systemd runs the jobs itself.
+pub fn generate_systemd_units(
+    systemd_args: &SystemdArgs,
+    jobs: Vec<ScheduleArgs>,
+) -> Result<Vec<SystemdUnits>, SystemdError> {
+    let scheduler_options = systemd_args.schedule.scheduler_options();
+    if !scheduler_options.is_empty() {
+        return Err(SystemdError::UnsupportedOptions(
+            scheduler_options.iter().map(|n| n.to_string()).collect(),
+        ));
+    }
+    let executable = env::current_exe().map_err(SystemdError::ExecutableNotFound)?;
+    let executable = executable.display().to_string();
+    let single_job = jobs.len() == 1;
+
+    jobs.iter()
+        .map(|job| {
+            let name = match single_job {
+                true => systemd_args.unit_name.to_owned(),
+                false => format!("{}-{}", systemd_args.unit_name, job.job_name()),
+            };
+            generate_units(systemd_args, &name, job, &executable)
+        })
+        .collect()
+}
+
+fn write_file(path: &Path, contents: &str, mode: u32) -> Result<(), SystemdError> {
+    let write_error = |e| SystemdError::WriteError(path.display().to_string(), e);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(write_error)?;
+    }
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(path)
+        .and_then(|mut n| std::io::Write::write_all(&mut n, contents.as_bytes()))
+        .map_err(write_error)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(write_error)
+}
+
+// Writes the units to the install directory, and the passphrase to its
+// credential file readable only by the owner. When no install directory
+// is given the units are printed instead, without the passphrase.
+pub fn install_systemd_units(
+    systemd_args: &SystemdArgs,
+    units: Vec<SystemdUnits>,
+) -> Result<(), SystemdError> {
+    let install_directory = match &systemd_args.install_directory {
+        Some(n) => Path::new(n),
+        None => {
+            units.iter().for_each(display_systemd_units);
+            return Ok(());
+        }
+    };
+
+    for unit in units {
+        let service_path = install_directory.join(format!("{}.service", unit.name));
+        let timer_path = install_directory.join(format!("{}.timer", unit.name));
+        write_file(&service_path, &unit.service, 0o644)?;
+        write_file(&timer_path, &unit.timer, 0o644)?;
+        if let Some(passphrase) = &unit.passphrase {
+            write_file(Path::new(&unit.credential_file), passphrase, 0o600)?;
+        }
+        util::log_print(
+            &format!(
+                "Installed {} and {}, enable with `systemctl enable --now {}.timer`",
+                service_path.display(),
+                timer_path.display(),
+                unit.name
+            ),
+            util::LogLevel::Info,
+        );
+    }
+    Ok(())
+}
+
+fn display_systemd_units(units: &SystemdUnits) {
+    util::log_print(
+        &format!("# {}.service\n{}", units.name, units.service),
+        util::LogLevel::Info,
+    );
+    util::log_print(
+        &format!("# {}.timer\n{}", units.name, units.timer),
+        util::LogLevel::Info,
+    );
+    if units.passphrase.is_some() {
+        util::log_print(
+            &format!(
+                "# Store the passphrase in {} readable only by root\n",
+                units.credential_file
+            ),
+            util::LogLevel::Info,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup_systemd_args(args: &[&str]) -> SystemdArgs {
+        let mut all_args = vec!["systemd", "--repository", "/backups/repository"];
+        all_args.extend(args);
+        SystemdArgs::parse_from(all_args)
+    }
+
+    #[test]
+    fn test_generate_systemd_units_scheduler_options_fail() {
+        let systemd_args = setup_systemd_args(&["--daemonize", "--pid-file", "/run/borg.pid"]);
+        match generate_systemd_units(&systemd_args, vec![systemd_args.schedule()]) {
+            Err(SystemdError::UnsupportedOptions(options)) => {
+                assert_eq!(options, ["--daemonize", "--pid-file"])
+            }
+            _ => panic!("Expected an unsupported options error"),
+        }
+
+        let systemd_args = setup_systemd_args(&["--missed-runs", "once"]);
+        assert!(generate_systemd_units(&systemd_args, vec![systemd_args.schedule()]).is_ok());
+    }
+
+    #[test]
+    fn test_translate_field() {
+        assert_eq!(translate_field(CronField::Minute, "*").unwrap(), "*");
+        assert_eq!(translate_field(CronField::Minute, "5").unwrap(), "05");
+        assert_eq!(translate_field(CronField::Minute, "*/15").unwrap(), "00/15");
+        assert_eq!(translate_field(CronField::Minute, "5/15").unwrap(), "05/15");
+        assert_eq!(translate_field(CronField::Hour, "1-3").unwrap(), "01..03");
+        assert_eq!(
+            translate_field(CronField::Hour, "1-7/3").unwrap(),
+            "01,04,07"
+        );
+        assert_eq!(translate_field(CronField::Hour, "1,13").unwrap(), "01,13");
+        assert_eq!(
+            translate_field(CronField::DayOfMonth, "*/2").unwrap(),
+            "1/2"
+        );
+        assert_eq!(
+            translate_field(CronField::Month, "Jan-Mar").unwrap(),
+            "1..3"
+        );
+        assert_eq!(translate_field(CronField::DayOfWeek, "1").unwrap(), "Sun");
+        assert_eq!(
+            translate_field(CronField::DayOfWeek, "mon-fri").unwrap(),
+            "Mon..Fri"
+        );
+        assert_eq!(
+            translate_field(CronField::DayOfWeek, "*/2").unwrap(),
+            "Sun,Tue,Thu,Sat"
+        );
+        assert!(translate_field(CronField::Hour, "25").is_err());
+        assert!(translate_field(CronField::Hour, "*/0").is_err());
+    }
+
+    #[test]
+    fn test_to_on_calendar() {
+        assert_eq!(
+            to_on_calendar("0 30 2 * * Mon-Fri", "Europe/London").unwrap(),
+            "Mon..Fri *-*-* 02:30:00 Europe/London"
+        );
+        assert_eq!(
+            to_on_calendar("0 0 0 1 Jan * 2030", "Etc/UTC").unwrap(),
+            "2030-1-1 00:00:00 Etc/UTC"
+        );
+        assert_eq!(
+            to_on_calendar("0 */15 * * * *", "Etc/UTC").unwrap(),
+            "*-*-* *:00/15:00 Etc/UTC"
+        );
+        assert!(to_on_calendar("not cron", "Etc/UTC").is_err());
+        assert!(to_on_calendar("0 0 0 * * L", "Etc/UTC").is_err());
+    }
+
+    #[test]
+    fn test_quote_exec_arg() {
+        assert_eq!(quote_exec_arg("/home"), "/home");
+        assert_eq!(quote_exec_arg("my files"), "\"my files\"");
+        assert_eq!(quote_exec_arg("100%"), "100%%");
+        assert_eq!(quote_exec_arg("$HOME"), "$$HOME");
+        assert_eq!(quote_exec_arg("a\"b c"), "\"a\\\"b c\"");
+    }
+
+    #[test]
+    fn test_generate_units() {
+        let systemd_args = setup_systemd_args(&[
+            "--passphrase",
+            "secret",
+            "--expression",
+            "0 0 2 * * *",
+            "--paths",
+            "/home",
+            "/etc",
+            "--keep-daily",
+            "7",
+            "--compact",
+            "--missed-runs",
+            "once",
+        ]);
+        let units = generate_units(
+            &systemd_args,
+            "rusty-borg",
+            &systemd_args.schedule(),
+            "/usr/bin/rusty_borg",
+        )
+        .unwrap();
+
+        assert!(!units.service.contains("secret"));
+        assert!(units
+            .service
+            .contains("LoadCredential=passphrase:/etc/rusty_borg/rusty-borg.passphrase"));
+        assert!(units.service.contains(
+            "ExecStart=/usr/bin/rusty_borg create /backups/repository --passphrase-file ${CREDENTIALS_DIRECTORY}/passphrase --paths /home /etc\n"
+        ));
+        assert!(units.service.contains(
+            "ExecStart=/usr/bin/rusty_borg prune /backups/repository --passphrase-file ${CREDENTIALS_DIRECTORY}/passphrase --keep-daily=7\n"
+        ));
+        assert!(units
+            .service
+            .contains("ExecStart=/usr/bin/rusty_borg compact /backups/repository\n"));
+        assert!(units.timer.contains(
+            "OnCalendar=*-*-* 02:00:00 Etc/UTC\nPersistent=true\nUnit=rusty-borg.service"
+        ));
+        assert_eq!(units.passphrase, Some("secret".to_owned()));
+    }
+
+    #[test]
+    fn test_generate_units_no_passphrase() {
+        let systemd_args = setup_systemd_args(&["--expression", "0 0 2 * * *"]);
+        let units = generate_units(
+            &systemd_args,
+            "rusty-borg",
+            &systemd_args.schedule(),
+            "/usr/bin/rusty_borg",
+        )
+        .unwrap();
+
+        assert!(!units.service.contains("LoadCredential"));
+        assert!(!units.service.contains("--passphrase-file"));
+        assert!(units.timer.contains("Persistent=false"));
+    }
+
+    #[test]
+    fn test_generate_units_remote() {
+        let systemd_args = setup_systemd_args(&[
+            "--expression",
+            "0 0 2 * * *",
+            "--remote-path",
+            "/usr/local/bin/borg",
+            "--compact",
+        ]);
+        let units = generate_units(
+            &systemd_args,
+            "rusty-borg",
+            &systemd_args.schedule(),
+            "/usr/bin/rusty_borg",
+        )
+        .unwrap();
+
+        assert!(units.service.contains(
+            "ExecStart=/usr/bin/rusty_borg create /backups/repository --remote-path=/usr/local/bin/borg\n"
+        ));
+        assert!(units.service.contains(
+            "ExecStart=/usr/bin/rusty_borg compact /backups/repository --remote-path=/usr/local/bin/borg\n"
+        ));
+    }
+
+    #[test]
+    fn test_generate_units_crontab_expression() {
+        let systemd_args = setup_systemd_args(&["--expression", "30 2 * * 1-5"]);
+        let units = generate_units(
+            &systemd_args,
+            "rusty-borg",
+            &systemd_args.schedule(),
+            "/usr/bin/rusty_borg",
+        )
+        .unwrap();
+
+        assert!(units
+            .timer
+            .contains("OnCalendar=Mon..Fri *-*-* 02:30:00 Etc/UTC"));
+    }
+
+    #[test]
+    fn test_generate_units_invalid_timezone_fail() {
+        let systemd_args =
+            setup_systemd_args(&["--expression", "0 0 2 * * *", "--timezone", "Mars/Olympus"]);
+        let result = generate_units(
+            &systemd_args,
+            "rusty-borg",
+            &systemd_args.schedule(),
+            "/usr/bin/rusty_borg",
+        );
+        assert!(matches!(result, Err(SystemdError::InvalidSchedule(_))));
+    }
+
+    #[test]
+    fn test_install_systemd_units() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let install_directory = install_dir.path().to_str().unwrap().to_owned();
+        let credential_directory = install_dir.path().join("credentials");
+        let systemd_args = setup_systemd_args(&[
+            "--passphrase",
+            "secret",
+            "--expression",
+            "0 0 2 * * *",
+            "--install-directory",
+            &install_directory,
+            "--credential-directory",
+            credential_directory.to_str().unwrap(),
+        ]);
+        let units = generate_systemd_units(&systemd_args, vec![systemd_args.schedule()]).unwrap();
+        install_systemd_units(&systemd_args, units).unwrap();
+
+        assert!(install_dir.path().join("rusty-borg.service").exists());
+        assert!(install_dir.path().join("rusty-borg.timer").exists());
+        let credential_file = credential_directory.join("rusty-borg.passphrase");
+        assert_eq!(fs::read_to_string(&credential_file).unwrap(), "secret");
+        let mode = fs::metadata(&credential_file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,391 @@
+use super::borg::errors::PassphraseError;
+use super::borg::passphrase;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+const CONFIG_FILE: &str = "rusty_borg/config.toml";
+
+// A named backup profile defined in the configuration file, e.g.
+//
+// [profiles.nightly]
+// repository = "/backups/repository"
+// passcommand = "pass show borg/nightly"
+// paths = ["/home", "/etc"]
+// exclude_patterns = ["**/.cache"]
+// expression = "0 0 2 * * *"
+// timezone = "Europe/London"
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub repository: Option<String>,
+    pub passphrase: Option<String>,
+    pub passphrase_env: Option<String>,
+    pub passphrase_file: Option<String>,
+    pub passcommand: Option<String>,
+    pub archive: Option<String>,
+    #[serde(default)]
+    pub paths: Vec<String>,
+    pub include_patterns: Option<Vec<String>>,
+    pub exclude_patterns: Option<Vec<String>>,
+    pub expression: Option<String>,
+    pub timezone: Option<String>,
+}
+
+impl Profile {
+    // Reads the passphrase from the source set in the profile, falling back
+    // to a plaintext `passphrase`. Only one of them can be set.
+    pub fn passphrase(&self, name: &str) -> Result<Option<String>, ConfigError> {
+        let sources = [
+            self.passphrase.is_some(),
+            self.passphrase_env.is_some(),
+            self.passphrase_file.is_some(),
+            self.passcommand.is_some(),
+        ];
+        if sources.iter().filter(|n| **n).count() > 1 {
+            return Err(ConfigError::ConflictingValues(
+                name.to_owned(),
+                "passphrase, passphrase_env, passphrase_file and passcommand".to_owned(),
+            ));
+        }
+
+        let passphrase = passphrase::read_source(
+            self.passphrase_env.as_deref(),
+            self.passphrase_file.as_deref(),
+            self.passcommand.as_deref(),
+            None,
+        )
+        .map_err(|e| ConfigError::PassphraseError(name.to_owned(), e))?;
+        Ok(passphrase.or_else(|| self.passphrase.to_owned()))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    NoConfigDirectory,
+    ReadError(PathBuf, std::io::Error),
+    ParseError(PathBuf, toml::de::Error),
+    ProfileNotFound(String),
+    MissingValue(String, String),
+    ConflictingValues(String, String),
+    PassphraseError(String, PassphraseError),
+    SharedJobOptions(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NoConfigDirectory => {
+                write!(
+                    f,
+                    "Unable to find the configuration directory, set --config"
+                )
+            }
+            ConfigError::ReadError(path, io_err) => {
+                write!(f, "Failed to read {}: {}", path.display(), io_err)
+            }
+            ConfigError::ParseError(path, toml_err) => {
+                write!(f, "Failed to parse {}: {}", path.display(), toml_err)
+            }
+            ConfigError::ProfileNotFound(name) => write!(f, "Profile '{}' not found", name),
+            ConfigError::MissingValue(name, value) => {
+                write!(f, "Profile '{}' is missing a {}", name, value)
+            }
+            ConfigError::ConflictingValues(name, values) => {
+                write!(f, "Profile '{}' can only set one of {}", name, values)
+            }
+            ConfigError::PassphraseError(name, passphrase_err) => {
+                write!(f, "Profile '{}': {}", name, passphrase_err)
+            }
+            ConfigError::SharedJobOptions(options) => write!(
+                f,
+                "{} would apply to every job, set them in each profile instead",
+                options.join(", ")
+            ),
+        }
+    }
+}
+
+// Implemented by the arguments of commands that can be resolved from a
+// profile. Values given on the command line take precedence over the profile.
+pub trait ProfileTrait: Sized {
+    fn profile(&self) -> Option<String>;
+    // The options given on the command line that a profile can also set.
+    fn profile_options(&self) -> Vec<&'static str>;
+    fn apply_profile(self, name: &str, profile: &Profile) -> Result<Self, ConfigError>;
+}
+
+// Returns the default configuration file location,
+// `$XDG_CONFIG_HOME/rusty_borg/config.toml` or `~/.config/rusty_borg/config.toml`.
+pub fn default_config_path() -> Result<PathBuf, ConfigError> {
+    match (env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME")) {
+        (Some(n), _) if !n.is_empty() => Ok(PathBuf::from(n).join(CONFIG_FILE)),
+        (_, Some(n)) if !n.is_empty() => Ok(PathBuf::from(n).join(".config").join(CONFIG_FILE)),
+        _ => Err(ConfigError::NoConfigDirectory),
+    }
+}
+
+// Reads and parses the configuration file at `config_path`,
+// or the default location if no path is given.
+pub fn load_config(config_path: Option<String>) -> Result<Config, ConfigError> {
+    let path = match config_path {
+        Some(n) => PathBuf::from(n),
+        None => default_config_path()?,
+    };
+    let contents =
+        fs::read_to_string(&path).map_err(|e| ConfigError::ReadError(path.clone(), e))?;
+    toml::from_str(&contents).map_err(|e| ConfigError::ParseError(path, e))
+}
+
+// Returns the absolute path of the configuration file given with
+// `--config`, so it can still be reloaded once a daemon has changed its
+// working directory. The default location is already absolute.
+pub fn absolute_config_path(config_path: Option<String>) -> Result<Option<String>, ConfigError> {
+    config_path
+        .map(|n| {
+            std::path::absolute(&n)
+                .map(|path| path.display().to_string())
+                .map_err(|e| ConfigError::ReadError(PathBuf::from(n), e))
+        })
+        .transpose()
+}
+
+// Resolves the profile selected in the arguments, if any, merging it into
+// the arguments so the result can be used like any other set of arguments.
+pub fn resolve_profile<T: ProfileTrait>(
+    args: T,
+    config_path: Option<String>,
+) -> Result<T, ConfigError> {
+    let name = match args.profile() {
+        Some(n) => n,
+        None => return Ok(args),
+    };
+
+    let config = load_config(config_path)?;
+    match config.profiles.get(&name) {
+        Some(profile) => args.apply_profile(&name, profile),
+        None => Err(ConfigError::ProfileNotFound(name)),
+    }
+}
+
+// Resolves each of the named profiles against a copy of the arguments,
+// e.g. to run several scheduled jobs from one set of options.
+//
+// With several profiles, options a profile can set aren't accepted on the
+// command line, as they would override every profile, e.g. pointing every
+// job at the same repository.
+pub fn resolve_profiles<T: ProfileTrait + Clone>(
+    args: T,
+    names: &[String],
+    config_path: Option<String>,
+) -> Result<Vec<T>, ConfigError> {
+    let profile_options = args.profile_options();
+    if names.len() > 1 && !profile_options.is_empty() {
+        return Err(ConfigError::SharedJobOptions(
+            profile_options.iter().map(|n| n.to_string()).collect(),
+        ));
+    }
+
+    let config = load_config(config_path)?;
+    names
+        .iter()
+        .map(|name| match config.profiles.get(name) {
+            Some(profile) => args.clone().apply_profile(name, profile),
+            None => Err(ConfigError::ProfileNotFound(name.to_owned())),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::util;
+    use super::*;
+
+    const CONFIG: &str = r#"
+[profiles.nightly]
+repository = "/backups/repository"
+passphrase = "passphrase"
+paths = ["/home", "/etc"]
+exclude_patterns = ["**/.cache"]
+expression = "0 0 2 * * *"
+timezone = "Europe/London"
+
+[profiles.empty]
+"#;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(config.profiles.len(), 2);
+
+        let nightly = config.profiles.get("nightly").unwrap();
+        assert_eq!(nightly.repository, Some("/backups/repository".to_owned()));
+        assert_eq!(nightly.paths, vec!["/home", "/etc"]);
+        assert_eq!(nightly.include_patterns, None);
+        assert_eq!(nightly.exclude_patterns, Some(vec!["**/.cache".to_owned()]));
+        assert_eq!(nightly.expression, Some("0 0 2 * * *".to_owned()));
+
+        let empty = config.profiles.get("empty").unwrap();
+        assert_eq!(empty.repository, None);
+        assert_eq!(empty.paths.len(), 0);
+    }
+
+    #[test]
+    fn test_parse_config_unknown_field_fail() {
+        let result = toml::from_str::<Config>("[profiles.nightly]\nrepo = \"test\"");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_profile_passphrase() {
+        let profile = Profile {
+            passcommand: Some("echo passphrase".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            profile.passphrase("nightly").unwrap(),
+            Some("passphrase".to_owned())
+        );
+
+        let profile = Profile {
+            passphrase: Some("plaintext".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            profile.passphrase("nightly").unwrap(),
+            Some("plaintext".to_owned())
+        );
+        assert_eq!(Profile::default().passphrase("nightly").unwrap(), None);
+
+        let profile = Profile {
+            passphrase_env: Some("RUSTY_BORG_TEST_PROFILE_MISSING".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            profile.passphrase("nightly"),
+            Err(ConfigError::PassphraseError(_, _))
+        ));
+
+        let profile = Profile {
+            passphrase: Some("plaintext".to_owned()),
+            passcommand: Some("echo passphrase".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            profile.passphrase("nightly"),
+            Err(ConfigError::ConflictingValues(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_load_config_pass() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let config_path = config_dir.path().join("config.toml");
+        fs::write(&config_path, CONFIG).unwrap();
+
+        let config = load_config(Some(config_path.to_str().unwrap().to_owned())).unwrap();
+        assert!(config.profiles.contains_key("nightly"));
+    }
+
+    #[test]
+    fn test_load_config_fail() {
+        let result = load_config(Some(util::get_random_string(10)));
+        assert!(matches!(result, Err(ConfigError::ReadError(_, _))));
+    }
+
+    #[test]
+    fn test_absolute_config_path() {
+        let config_path = absolute_config_path(Some("rusty_borg.toml".to_owned()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            PathBuf::from(&config_path),
+            env::current_dir().unwrap().join("rusty_borg.toml")
+        );
+
+        let result = absolute_config_path(Some("/etc/rusty_borg.toml".to_owned())).unwrap();
+        assert_eq!(result, Some("/etc/rusty_borg.toml".to_owned()));
+        assert_eq!(absolute_config_path(None).unwrap(), None);
+    }
+
+    #[derive(Clone)]
+    struct TestArgs {
+        repository: Option<String>,
+    }
+
+    impl ProfileTrait for TestArgs {
+        fn profile(&self) -> Option<String> {
+            None
+        }
+
+        fn profile_options(&self) -> Vec<&'static str> {
+            match self.repository {
+                Some(_) => vec!["--repository"],
+                None => Vec::new(),
+            }
+        }
+
+        fn apply_profile(self, name: &str, profile: &Profile) -> Result<Self, ConfigError> {
+            match self.repository.or_else(|| profile.repository.to_owned()) {
+                Some(n) => Ok(TestArgs {
+                    repository: Some(n),
+                }),
+                None => Err(ConfigError::MissingValue(
+                    name.to_owned(),
+                    "repository".to_owned(),
+                )),
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_profiles() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let config_path = config_dir.path().join("config.toml");
+        fs::write(&config_path, CONFIG).unwrap();
+        let config_path = Some(config_path.to_str().unwrap().to_owned());
+        let args = TestArgs { repository: None };
+
+        let result = resolve_profiles(args.clone(), &["nightly".to_owned()], config_path.clone());
+        assert_eq!(
+            result.unwrap()[0].repository,
+            Some("/backups/repository".to_owned())
+        );
+
+        let result = resolve_profiles(
+            args.clone(),
+            &["nightly".to_owned(), "empty".to_owned()],
+            config_path.clone(),
+        );
+        assert!(matches!(result, Err(ConfigError::MissingValue(_, _))));
+
+        let result = resolve_profiles(args, &["missing".to_owned()], config_path.clone());
+        assert!(matches!(result, Err(ConfigError::ProfileNotFound(_))));
+
+        let args = TestArgs {
+            repository: Some("/override".to_owned()),
+        };
+        let result = resolve_profiles(args.clone(), &["nightly".to_owned()], config_path.clone());
+        assert_eq!(result.unwrap()[0].repository, Some("/override".to_owned()));
+
+        let result = resolve_profiles(
+            args,
+            &["nightly".to_owned(), "empty".to_owned()],
+            config_path,
+        );
+        match result {
+            Err(ConfigError::SharedJobOptions(options)) => assert_eq!(options, ["--repository"]),
+            _ => panic!("Expected a shared job options error"),
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use super::borg::check::CheckResult;
+use super::borg::errors::{
+    ArchiveError, CheckError, CommandError, DaemonError, ExtractError, PassphraseError,
+    RemoteError, RepositoryError, RetentionError, ScheduleError, SystemdError,
+};
+use super::config::ConfigError;
+use super::history::HistoryError;
+use borgbackup::errors::{InitError, ListError};
+use borgbackup::output::logging::MessageId;
+use std::fmt;
+
+// The exit codes of RustyBorg, documented in the README so wrappers can
+// react to a failure without parsing its message. Like borg, 1 means the
+// command completed with warnings and 2 is any other error.
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_WARNING: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
+pub const EXIT_PASSPHRASE_WRONG: i32 = 3;
+pub const EXIT_REPOSITORY_MISSING: i32 = 4;
+pub const EXIT_LOCK_HELD: i32 = 5;
+pub const EXIT_CONNECTION_FAILED: i32 = 6;
+pub const EXIT_REPOSITORY_CORRUPT: i32 = 7;
+
+// The error returned by any command, wrapping the error of the module
+// that failed so `main` can report it and pick the exit code.
+#[derive(Debug)]
+pub enum RustyBorgError {
+    Archive(ArchiveError),
+    Repository(RepositoryError),
+    List(ListError),
+    Command(CommandError),
+    Retention(RetentionError),
+    Check(CheckError),
+    Extract(ExtractError),
+    Passphrase(PassphraseError),
+    Config(ConfigError),
+    Schedule(ScheduleError),
+    Systemd(SystemdError),
+    History(HistoryError),
+    Remote(RemoteError),
+}
+
+impl fmt::Display for RustyBorgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RustyBorgError::Archive(err) => write!(f, "{}", err),
+            RustyBorgError::Repository(err) => write!(f, "{}", err),
+            RustyBorgError::List(err) => write!(f, "{}", err),
+            RustyBorgError::Command(err) => write!(f, "{}", err),
+            RustyBorgError::Retention(err) => write!(f, "{}", err),
+            RustyBorgError::Check(err) => write!(f, "{}", err),
+            RustyBorgError::Extract(err) => write!(f, "{}", err),
+            RustyBorgError::Passphrase(err) => write!(f, "{}", err),
+            RustyBorgError::Config(err) => write!(f, "{}", err),
+            RustyBorgError::Schedule(err) => write!(f, "{}", err),
+            RustyBorgError::Systemd(err) => write!(f, "{}", err),
+            RustyBorgError::History(err) => write!(f, "{}", err),
+            RustyBorgError::Remote(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl RustyBorgError {
+    // The exit code for the error, see the `EXIT_` constants.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RustyBorgError::Archive(ArchiveError::PartialBackup(_, _)) => EXIT_WARNING,
+            RustyBorgError::Archive(ArchiveError::ArchiveCreateError(err)) => {
+                command_exit_code(err)
+            }
+            RustyBorgError::Repository(RepositoryError::RepositoryInitError(
+                InitError::UnexpectedMessageId(msg_id),
+            )) => message_id_exit_code(msg_id),
+            RustyBorgError::List(err) => match err {
+                ListError::PassphraseWrong => EXIT_PASSPHRASE_WRONG,
+                ListError::RepositoryDoesNotExist => EXIT_REPOSITORY_MISSING,
+                ListError::UnexpectedMessageId(msg_id) => message_id_exit_code(msg_id),
+                _ => EXIT_ERROR,
+            },
+            RustyBorgError::Command(err) => command_exit_code(err),
+            RustyBorgError::Retention(RetentionError::PruneCommandError(err)) => {
+                command_exit_code(err)
+            }
+            RustyBorgError::Check(CheckError::CheckCommandError(err)) => command_exit_code(err),
+            RustyBorgError::Extract(ExtractError::ExtractCommandError(err)) => {
+                command_exit_code(err)
+            }
+            RustyBorgError::Schedule(ScheduleError::ScheduleDaemonError(
+                DaemonError::AlreadyRunning(_, _),
+            )) => EXIT_LOCK_HELD,
+            RustyBorgError::Remote(RemoteError::InvalidRepository(_, _)) => EXIT_ERROR,
+            RustyBorgError::Remote(_) => EXIT_CONNECTION_FAILED,
+            _ => EXIT_ERROR,
+        }
+    }
+
+    // A short name for the error reported in JSON output, naming the
+    // failure for errors with their own exit code and otherwise the
+    // command or module that failed.
+    pub fn kind(&self) -> &'static str {
+        match self.exit_code() {
+            EXIT_WARNING => "partial_backup",
+            EXIT_PASSPHRASE_WRONG => "passphrase_wrong",
+            EXIT_REPOSITORY_MISSING => "repository_does_not_exist",
+            EXIT_LOCK_HELD => "lock_held",
+            EXIT_CONNECTION_FAILED => "connection_failed",
+            _ => match self {
+                RustyBorgError::Archive(_) => "create_failed",
+                RustyBorgError::Repository(_) => "init_failed",
+                RustyBorgError::List(_) => "list_failed",
+                RustyBorgError::Command(_) => "command_failed",
+                RustyBorgError::Retention(_) => "prune_failed",
+                RustyBorgError::Check(_) => "check_failed",
+                RustyBorgError::Extract(_) => "extract_failed",
+                RustyBorgError::Passphrase(_) => "passphrase_source_failed",
+                RustyBorgError::Config(_) => "config_error",
+                RustyBorgError::Schedule(_) => "schedule_error",
+                RustyBorgError::Systemd(_) => "systemd_error",
+                RustyBorgError::History(_) => "history_error",
+                RustyBorgError::Remote(_) => "remote_error",
+            },
+        }
+    }
+}
+
+// The exit code for a check that ran, problems found in the repository
+// have their own code so they aren't mistaken for a warning.
+pub fn check_exit_code(check_result: &CheckResult) -> i32 {
+    match check_result.passed {
+        true => EXIT_SUCCESS,
+        false => EXIT_REPOSITORY_CORRUPT,
+    }
+}
+
+fn command_exit_code(command_err: &CommandError) -> i32 {
+    match command_err {
+        CommandError::PassphraseWrong => EXIT_PASSPHRASE_WRONG,
+        CommandError::RepositoryDoesNotExist => EXIT_REPOSITORY_MISSING,
+        CommandError::LockFailed => EXIT_LOCK_HELD,
+        CommandError::ConnectionClosed => EXIT_CONNECTION_FAILED,
+        _ => EXIT_ERROR,
+    }
+}
+
+fn message_id_exit_code(msg_id: &MessageId) -> i32 {
+    match msg_id {
+        MessageId::PassphraseWrong => EXIT_PASSPHRASE_WRONG,
+        MessageId::RepositoryDoesNotExist => EXIT_REPOSITORY_MISSING,
+        MessageId::LockError | MessageId::LockErrorT => EXIT_LOCK_HELD,
+        MessageId::ConnectionClosed => EXIT_CONNECTION_FAILED,
+        _ => EXIT_ERROR,
+    }
+}
+
+impl From<ArchiveError> for RustyBorgError {
+    fn from(err: ArchiveError) -> Self {
+        RustyBorgError::Archive(err)
+    }
+}
+
+impl From<RepositoryError> for RustyBorgError {
+    fn from(err: RepositoryError) -> Self {
+        RustyBorgError::Repository(err)
+    }
+}
+
+impl From<ListError> for RustyBorgError {
+    fn from(err: ListError) -> Self {
+        RustyBorgError::List(err)
+    }
+}
+
+impl From<CommandError> for RustyBorgError {
+    fn from(err: CommandError) -> Self {
+        RustyBorgError::Command(err)
+    }
+}
+
+impl From<RetentionError> for RustyBorgError {
+    fn from(err: RetentionError) -> Self {
+        RustyBorgError::Retention(err)
+    }
+}
+
+impl From<CheckError> for RustyBorgError {
+    fn from(err: CheckError) -> Self {
+        RustyBorgError::Check(err)
+    }
+}
+
+impl From<ExtractError> for RustyBorgError {
+    fn from(err: ExtractError) -> Self {
+        RustyBorgError::Extract(err)
+    }
+}
+
+impl From<PassphraseError> for RustyBorgError {
+    fn from(err: PassphraseError) -> Self {
+        RustyBorgError::Passphrase(err)
+    }
+}
+
+impl From<ConfigError> for RustyBorgError {
+    fn from(err: ConfigError) -> Self {
+        RustyBorgError::Config(err)
+    }
+}
+
+impl From<ScheduleError> for RustyBorgError {
+    fn from(err: ScheduleError) -> Self {
+        RustyBorgError::Schedule(err)
+    }
+}
+
+impl From<SystemdError> for RustyBorgError {
+    fn from(err: SystemdError) -> Self {
+        RustyBorgError::Systemd(err)
+    }
+}
+
+impl From<HistoryError> for RustyBorgError {
+    fn from(err: HistoryError) -> Self {
+        RustyBorgError::History(err)
+    }
+}
+
+impl From<RemoteError> for RustyBorgError {
+    fn from(err: RemoteError) -> Self {
+        RustyBorgError::Remote(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_exit_code_command_errors() {
+        let result1 = RustyBorgError::from(CommandError::PassphraseWrong);
+        assert_eq!(result1.exit_code(), EXIT_PASSPHRASE_WRONG);
+
+        let result2 = RustyBorgError::from(RetentionError::PruneCommandError(
+            CommandError::RepositoryDoesNotExist,
+        ));
+        assert_eq!(result2.exit_code(), EXIT_REPOSITORY_MISSING);
+
+        let result3 =
+            RustyBorgError::from(ArchiveError::ArchiveCreateError(CommandError::LockFailed));
+        assert_eq!(result3.exit_code(), EXIT_LOCK_HELD);
+
+        let result4 = RustyBorgError::from(CheckError::CheckCommandError(
+            CommandError::ConnectionClosed,
+        ));
+        assert_eq!(result4.exit_code(), EXIT_CONNECTION_FAILED);
+
+        let result5 = RustyBorgError::from(CommandError::Unknown("message".to_owned()));
+        assert_eq!(result5.exit_code(), EXIT_ERROR);
+    }
+
+    #[test]
+    fn test_exit_code_borgbackup_errors() {
+        assert_eq!(
+            RustyBorgError::from(ListError::PassphraseWrong).exit_code(),
+            EXIT_PASSPHRASE_WRONG
+        );
+        assert_eq!(
+            RustyBorgError::from(ListError::RepositoryDoesNotExist).exit_code(),
+            EXIT_REPOSITORY_MISSING
+        );
+        assert_eq!(
+            RustyBorgError::from(ListError::UnexpectedMessageId(MessageId::LockErrorT)).exit_code(),
+            EXIT_LOCK_HELD
+        );
+        assert_eq!(
+            RustyBorgError::from(RepositoryError::RepositoryInitError(
+                InitError::RepositoryAlreadyExists
+            ))
+            .exit_code(),
+            EXIT_ERROR
+        );
+    }
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(
+            RustyBorgError::from(CommandError::LockFailed).kind(),
+            "lock_held"
+        );
+        assert_eq!(
+            RustyBorgError::from(ListError::PassphraseWrong).kind(),
+            "passphrase_wrong"
+        );
+        assert_eq!(
+            RustyBorgError::from(CheckError::RepairNotConfirmed).kind(),
+            "check_failed"
+        );
+        assert_eq!(
+            RustyBorgError::from(PassphraseError::EmptyPassphrase).kind(),
+            "passphrase_source_failed"
+        );
+    }
+
+    #[test]
+    fn test_check_exit_code() {
+        let passed = CheckResult {
+            passed: true,
+            problems: Vec::new(),
+        };
+        assert_eq!(check_exit_code(&passed), EXIT_SUCCESS);
+
+        let failed = CheckResult {
+            passed: false,
+            problems: vec!["problem".to_owned()],
+        };
+        assert_eq!(check_exit_code(&failed), EXIT_REPOSITORY_CORRUPT);
+
+        let result = RustyBorgError::from(CheckError::RepairNotConfirmed);
+        assert_eq!(result.exit_code(), EXIT_ERROR);
+    }
+
+    #[test]
+    fn test_exit_code_other_errors() {
+        let result1 = RustyBorgError::from(ScheduleError::ScheduleDaemonError(
+            DaemonError::AlreadyRunning("/tmp/rusty_borg.pid".to_owned(), None),
+        ));
+        assert_eq!(result1.exit_code(), EXIT_LOCK_HELD);
+
+        let result2 = RustyBorgError::from(RemoteError::ConnectionFailed(
+            "localhost:22".to_owned(),
+            std::io::Error::from(std::io::ErrorKind::ConnectionRefused),
+        ));
+        assert_eq!(result2.exit_code(), EXIT_CONNECTION_FAILED);
+
+        let result3 = RustyBorgError::from(RemoteError::InvalidRepository(
+            "ssh://host".to_owned(),
+            "expected a path after the host".to_owned(),
+        ));
+        assert_eq!(result3.exit_code(), EXIT_ERROR);
+
+        let result4 = RustyBorgError::from(PassphraseError::EmptyPassphrase);
+        assert_eq!(result4.exit_code(), EXIT_ERROR);
+    }
+}
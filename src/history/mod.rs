@@ -0,0 +1,373 @@
+use crate::util;
+use borgbackup::output::info::ArchiveStats;
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const HISTORY_FILE: &str = "rusty_borg/history.jsonl";
+
+// Whether a scheduled run created its archive.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunStatus {
+    Success,
+    Failed,
+}
+
+// The reason a scheduled run failed, `kind` is a short identifier that
+// can be matched on while `message` is meant for people.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunError {
+    pub kind: String,
+    pub message: String,
+}
+
+impl RunError {
+    pub fn new(kind: &str, error: &impl fmt::Display) -> RunError {
+        RunError {
+            kind: kind.to_owned(),
+            message: error.to_string(),
+        }
+    }
+}
+
+// A single run of a scheduled job, stored as one line of JSON
+// in the history file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub job: String,
+    pub repository: String,
+    pub status: RunStatus,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub duration: f64,
+    pub archive: Option<String>,
+    pub stats: Option<ArchiveStats>,
+    pub error: Option<RunError>,
+}
+
+impl RunRecord {
+    pub fn new(
+        job: &str,
+        repository: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        result: Result<(String, ArchiveStats), RunError>,
+    ) -> RunRecord {
+        let (status, archive, stats, error) = match result {
+            Ok((archive, stats)) => (RunStatus::Success, Some(archive), Some(stats), None),
+            Err(e) => (RunStatus::Failed, None, None, Some(e)),
+        };
+
+        RunRecord {
+            job: job.to_owned(),
+            repository: repository.to_owned(),
+            status,
+            start,
+            end,
+            duration: (end - start).num_milliseconds() as f64 / 1000.0,
+            archive,
+            stats,
+            error,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HistoryError {
+    NoStateDirectory,
+    ReadError(PathBuf, io::Error),
+    WriteError(PathBuf, io::Error),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::NoStateDirectory => {
+                write!(f, "Unable to find the state directory, set --history-file")
+            }
+            HistoryError::ReadError(path, io_err) => {
+                write!(f, "Failed to read {}: {}", path.display(), io_err)
+            }
+            HistoryError::WriteError(path, io_err) => {
+                write!(f, "Failed to write {}: {}", path.display(), io_err)
+            }
+        }
+    }
+}
+
+// Struct for managing the necessary arguments for listing past scheduled runs.
+#[derive(Debug, Clone, Parser, Default)]
+pub struct HistoryArgs {
+    #[arg(long)]
+    history_file: Option<String>,
+    #[arg(short, long)]
+    job: Option<String>,
+    #[arg(short, long, value_enum)]
+    status: Option<RunStatus>,
+    #[arg(long, value_parser = parse_since)]
+    since: Option<DateTime<Utc>>,
+    #[arg(short = 'n', long)]
+    limit: Option<usize>,
+}
+
+// Parses the start of the time range to list, either a date such as
+// `2025-01-31` or an RFC 3339 timestamp.
+fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(n) = DateTime::parse_from_rfc3339(value) {
+        return Ok(n.with_timezone(&Utc));
+    }
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(n) => Ok(n.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()),
+        Err(_) => Err(format!(
+            "Invalid time '{}', expected a date such as 2025-01-31 or an RFC 3339 timestamp",
+            value
+        )),
+    }
+}
+
+// Returns the history file location, the given path or
+// `$XDG_STATE_HOME/rusty_borg/history.jsonl` or `~/.local/state/rusty_borg/history.jsonl`.
+pub fn history_path(history_file: Option<String>) -> Result<PathBuf, HistoryError> {
+    if let Some(n) = history_file {
+        return Ok(PathBuf::from(n));
+    }
+    match (env::var_os("XDG_STATE_HOME"), env::var_os("HOME")) {
+        (Some(n), _) if !n.is_empty() => Ok(PathBuf::from(n).join(HISTORY_FILE)),
+        (_, Some(n)) if !n.is_empty() => Ok(PathBuf::from(n)
+            .join(".local")
+            .join("state")
+            .join(HISTORY_FILE)),
+        _ => Err(HistoryError::NoStateDirectory),
+    }
+}
+
+// Appends a run to the history file, creating the file and its
+// parent directories if they don't exist.
+pub fn append_record(path: &Path, record: &RunRecord) -> Result<(), HistoryError> {
+    let write_error = |e| HistoryError::WriteError(path.to_path_buf(), e);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(write_error)?;
+    }
+
+    let mut line = serde_json::to_string(record).map_err(|e| write_error(e.into()))?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut n| n.write_all(line.as_bytes()))
+        .map_err(write_error)
+}
+
+// Reads every run from the history file, oldest first. A missing file
+// means nothing has run yet, and lines that can't be parsed (e.g. one
+// cut short by a crash) are skipped.
+pub fn read_records(path: &Path) -> Result<Vec<RunRecord>, HistoryError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(n) => n,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(HistoryError::ReadError(path.to_path_buf(), e)),
+    };
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+// Returns the start of the most recent successful run of a job.
+pub fn last_success(records: &[RunRecord], job: &str) -> Option<DateTime<Utc>> {
+    records
+        .iter()
+        .filter(|n| n.job == job && n.status == RunStatus::Success)
+        .map(|n| n.start)
+        .max()
+}
+
+// Keeps the runs matching the job, status and start time filters,
+// limited to the most recent `limit` runs.
+fn filter_records(history_args: &HistoryArgs, records: Vec<RunRecord>) -> Vec<RunRecord> {
+    let mut records: Vec<RunRecord> = records
+        .into_iter()
+        .filter(|n| history_args.job.as_ref().is_none_or(|job| &n.job == job))
+        .filter(|n| history_args.status.is_none_or(|status| n.status == status))
+        .filter(|n| history_args.since.is_none_or(|since| n.start >= since))
+        .collect();
+
+    if let Some(limit) = history_args.limit {
+        let skip = records.len().saturating_sub(limit);
+        records.drain(..skip);
+    }
+    records
+}
+
+// The entrypoint for listing past scheduled runs from the history file.
+pub fn list_history(history_args: &HistoryArgs) -> Result<Vec<RunRecord>, HistoryError> {
+    let path = history_path(history_args.history_file.to_owned())?;
+    let records = read_records(&path)?;
+    Ok(filter_records(history_args, records))
+}
+
+pub fn display_history(records: Vec<RunRecord>) {
+    if records.is_empty() {
+        util::log_print("No scheduled runs found", util::LogLevel::Warn);
+        return;
+    }
+    records.iter().for_each(|record| {
+        let outcome = match (&record.archive, &record.error) {
+            (_, Some(error)) => format!("{}: {}", error.kind, error.message),
+            (Some(archive), None) => match &record.stats {
+                Some(stats) => format!(
+                    "{} ({} files, {} bytes deduplicated)",
+                    archive, stats.nfiles, stats.deduplicated_size
+                ),
+                None => archive.to_owned(),
+            },
+            (None, None) => String::new(),
+        };
+        util::log_print(
+            &format!(
+                "{} {} {:?} {:.1}s {}",
+                record.start.to_rfc3339(),
+                record.job,
+                record.status,
+                record.duration,
+                outcome
+            ),
+            util::LogLevel::Info,
+        );
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup_record(job: &str, start: &str, result: Result<(), RunError>) -> RunRecord {
+        let start = DateTime::parse_from_rfc3339(start)
+            .unwrap()
+            .with_timezone(&Utc);
+        let end = start + chrono::Duration::seconds(90);
+        let stats = ArchiveStats {
+            compressed_size: 10,
+            deduplicated_size: 5,
+            nfiles: 2,
+            original_size: 20,
+        };
+        RunRecord::new(
+            job,
+            "repository",
+            start,
+            end,
+            result.map(|_| ("archive".to_owned(), stats)),
+        )
+    }
+
+    fn setup_records() -> Vec<RunRecord> {
+        let error = RunError {
+            kind: "passphrase_wrong".to_owned(),
+            message: "Invalid passphrase".to_owned(),
+        };
+        vec![
+            setup_record("nightly", "2025-01-01T02:00:00Z", Ok(())),
+            setup_record("hourly", "2025-01-01T03:00:00Z", Err(error)),
+            setup_record("nightly", "2025-01-02T02:00:00Z", Ok(())),
+        ]
+    }
+
+    #[test]
+    fn test_run_record_new() {
+        let records = setup_records();
+        assert_eq!(records[0].status, RunStatus::Success);
+        assert_eq!(records[0].duration, 90.0);
+        assert_eq!(records[0].archive, Some("archive".to_owned()));
+        assert!(records[0].error.is_none());
+
+        assert_eq!(records[1].status, RunStatus::Failed);
+        assert_eq!(records[1].archive, None);
+        assert_eq!(records[1].error.as_ref().unwrap().kind, "passphrase_wrong");
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let result1 = parse_since("2025-01-31").unwrap();
+        assert_eq!(result1.to_rfc3339(), "2025-01-31T00:00:00+00:00");
+
+        let result2 = parse_since("2025-01-31T12:00:00+01:00").unwrap();
+        assert_eq!(result2.to_rfc3339(), "2025-01-31T11:00:00+00:00");
+
+        assert!(parse_since("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_filter_records() {
+        let mut history_args = HistoryArgs {
+            job: Some("nightly".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(filter_records(&history_args, setup_records()).len(), 2);
+
+        history_args.limit = Some(1);
+        let result = filter_records(&history_args, setup_records());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].start.to_rfc3339(), "2025-01-02T02:00:00+00:00");
+
+        let history_args = HistoryArgs {
+            status: Some(RunStatus::Failed),
+            ..Default::default()
+        };
+        let result = filter_records(&history_args, setup_records());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].job, "hourly");
+
+        let history_args = HistoryArgs {
+            since: Some(parse_since("2025-01-02").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(filter_records(&history_args, setup_records()).len(), 1);
+    }
+
+    #[test]
+    fn test_last_success() {
+        let records = setup_records();
+        let result = last_success(&records, "nightly").unwrap();
+        assert_eq!(result.to_rfc3339(), "2025-01-02T02:00:00+00:00");
+
+        assert_eq!(last_success(&records, "hourly"), None);
+        assert_eq!(last_success(&records, "missing"), None);
+    }
+
+    #[test]
+    fn test_append_and_read_records() {
+        let history_dir = tempfile::tempdir().unwrap();
+        let path = history_dir.path().join("state").join("history.jsonl");
+        assert_eq!(read_records(&path).unwrap().len(), 0);
+
+        for record in setup_records() {
+            append_record(&path, &record).unwrap();
+        }
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"job\": \"trunc")
+            .unwrap();
+
+        let result = read_records(&path).unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[1].job, "hourly");
+        assert_eq!(result[2].stats.as_ref().unwrap().nfiles, 2);
+    }
+
+    #[test]
+    fn test_history_path() {
+        let result = history_path(Some("history.jsonl".to_owned())).unwrap();
+        assert_eq!(result, PathBuf::from("history.jsonl"));
+    }
+}
@@ -1,6 +1,10 @@
 use clap::{Parser, Subcommand};
+use std::process;
 
 mod borg;
+mod config;
+mod errors;
+mod history;
 mod util;
 
 #[derive(Parser, Debug)]
@@ -11,45 +15,221 @@ mod util;
 struct Args {
     // #[arg(short, long, default_value_t = false)]
     // daemonize: bool,
+    #[arg(long, global = true, value_enum, default_value_t = util::OutputFormat::Text)]
+    output: util::OutputFormat,
+    #[arg(long, global = true)]
+    config: Option<String>,
     #[command(subcommand)]
     cmd: Commands,
 }
 
 #[derive(Subcommand, Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     Init(borg::init::InitArgs),
     Create(borg::create::CreateArgs),
     List(borg::list::ListArgs),
-    Verify(util::VerifyArgs),
+    Prune(borg::prune::PruneArgs),
+    Compact(borg::compact::CompactArgs),
+    Check(borg::check::CheckArgs),
+    Extract(borg::extract::ExtractArgs),
+    Verify(util::verify::VerifyArgs),
     Schedule(borg::schedule::ScheduleArgs),
+    Systemd(borg::systemd::SystemdArgs),
+    History(history::HistoryArgs),
 }
 
-fn main() {
-    env_logger::init();
-    let args = Args::parse();
+// Reads the passphrase from the selected source for commands that take one,
+// before any profile is applied so the source takes precedence.
+fn resolve_passphrase_source(cmd: Commands) -> Result<Commands, borg::errors::PassphraseError> {
+    use borg::passphrase::resolve_passphrase;
+
+    Ok(match cmd {
+        Commands::Init(n) => Commands::Init(resolve_passphrase(n)?),
+        Commands::Create(n) => Commands::Create(resolve_passphrase(n)?),
+        Commands::List(n) => Commands::List(resolve_passphrase(n)?),
+        Commands::Prune(n) => Commands::Prune(resolve_passphrase(n)?),
+        Commands::Check(n) => Commands::Check(resolve_passphrase(n)?),
+        Commands::Extract(n) => Commands::Extract(resolve_passphrase(n)?),
+        Commands::Schedule(n) => Commands::Schedule(resolve_passphrase(n)?),
+        Commands::Systemd(n) => Commands::Systemd(resolve_passphrase(n)?),
+        other => other,
+    })
+}
+
+// Resolves the scheduled jobs, one for each profile given with `--jobs`
+// or a single job from the arguments and optional `--profile`.
+fn resolve_schedule_jobs(
+    schedule_args: borg::schedule::ScheduleArgs,
+    config_path: Option<String>,
+) -> Result<Vec<borg::schedule::ScheduleArgs>, config::ConfigError> {
+    let jobs = schedule_args.jobs();
+    match jobs.is_empty() {
+        true => config::resolve_profile(schedule_args, config_path).map(|n| vec![n]),
+        false => config::resolve_profiles(schedule_args, &jobs, config_path),
+    }
+}
+
+// Reports an error in the selected output format.
+fn report_error(output: util::OutputFormat, err: &errors::RustyBorgError) {
+    let message = format!("Operation failed: {}", err);
+    match output {
+        util::OutputFormat::Text => util::log_print(&message, util::LogLevel::Error),
+        util::OutputFormat::Json => util::json_error_print(&message, err.kind(), err.exit_code()),
+    }
+}
+
+// Runs the selected command, returning the exit code on success. Only
+// commands that can complete with warnings, or that report the outcome
+// of their own checks like `check` and `verify`, return something other
+// than `EXIT_SUCCESS`, every other failure is returned as an error.
+fn run(args: Args) -> Result<i32, errors::RustyBorgError> {
+    let output = args.output;
+    let cmd = resolve_passphrase_source(args.cmd)?;
 
-    match args.cmd {
-        Commands::Init(init_args) => match borg::init::initialise_repository(&init_args) {
-            Ok(_) => util::log_print("Repository successfully created", util::LogLevel::Info),
-            Err(e) => util::log_print(&format!("Operation failed: {}", e), util::LogLevel::Error),
-        },
-        Commands::Create(create_args) => match borg::create::create_archive(&create_args) {
-            Ok(n) => borg::create::display_create_info(n),
-            Err(err) => borg::errors::parse_archive_error(err),
-        },
-        Commands::List(list_args) => match borg::list::list_contents(list_args) {
-            Ok(()) => (),
-            Err(err) => {
-                util::log_print(&format!("{:?}", err), util::LogLevel::Error);
+    match cmd {
+        Commands::Init(init_args) => {
+            borg::remote::preflight_check(&init_args)?;
+            let encryption = borg::init::initialise_repository(&init_args)?;
+            match output {
+                util::OutputFormat::Text => util::log_print(
+                    &format!(
+                        "Repository successfully created with {} encryption",
+                        encryption
+                    ),
+                    util::LogLevel::Info,
+                ),
+                util::OutputFormat::Json => util::json_print(&borg::init::InitResult {
+                    repository: init_args.repository,
+                    encryption,
+                }),
+            }
+        }
+        Commands::Create(create_args) => {
+            let create_args = config::resolve_profile(create_args, args.config)?;
+            borg::remote::preflight_check(&create_args)?;
+            match borg::create::create_archive(&create_args) {
+                Ok(n) => match output {
+                    util::OutputFormat::Text => borg::create::display_create_info(n),
+                    util::OutputFormat::Json => util::json_print(&n),
+                },
+                Err(borg::errors::ArchiveError::PartialBackup(n, warnings)) => {
+                    match output {
+                        util::OutputFormat::Text => {
+                            warnings
+                                .iter()
+                                .for_each(|warning| util::log_print(warning, util::LogLevel::Warn));
+                            borg::create::display_create_info(*n);
+                        }
+                        util::OutputFormat::Json => {
+                            util::json_print(&borg::create::PartialBackup {
+                                archive: *n,
+                                warnings,
+                            })
+                        }
+                    }
+                    return Ok(errors::EXIT_WARNING);
+                }
+                Err(e) => return Err(e.into()),
             }
-        },
+        }
+        Commands::List(list_args) => {
+            borg::remote::preflight_check(&list_args)?;
+            match list_args.archive_name() {
+                Some(_) => {
+                    let entries = borg::list::list_archive_contents(&list_args)?;
+                    match output {
+                        util::OutputFormat::Text => borg::list::display_archive_entries(entries),
+                        util::OutputFormat::Json => util::json_print(&entries),
+                    }
+                }
+                None => {
+                    let repository = borg::list::list_contents(&list_args)?;
+                    match output {
+                        util::OutputFormat::Text => {
+                            borg::list::display_list_info(&list_args, repository)
+                        }
+                        util::OutputFormat::Json => util::json_print(&repository),
+                    }
+                }
+            }
+        }
+        Commands::Prune(prune_args) => {
+            borg::remote::preflight_check(&prune_args)?;
+            borg::prune::display_prune_info(borg::prune::prune_archives(&prune_args)?)
+        }
+        Commands::Compact(compact_args) => {
+            borg::remote::preflight_check(&compact_args)?;
+            borg::compact::display_compact_info(borg::compact::compact_repository(&compact_args)?)
+        }
+        Commands::Check(check_args) => {
+            borg::remote::preflight_check(&check_args)?;
+            let result = borg::check::check_repository(&check_args)?;
+            borg::check::display_check_info(&result);
+            return Ok(errors::check_exit_code(&result));
+        }
+        Commands::Extract(extract_args) => {
+            borg::remote::preflight_check(&extract_args)?;
+            borg::extract::display_extract_info(borg::extract::extract_archive(&extract_args)?)
+        }
         Commands::Verify(verify_args) => {
-            let test_con = util::verify_connection(verify_args);
-            match test_con {
-                Ok(_) => println!("Connection verified!"),
-                Err(e) => util::log_print(&format!("{}", e), util::LogLevel::Error),
+            let credentials = verify_args.credentials()?;
+            let result = util::verify::verify_connection(&verify_args, &credentials);
+            match output {
+                util::OutputFormat::Text => util::verify::display_verify_info(&result),
+                util::OutputFormat::Json => util::json_print(&result),
+            }
+            if !result.passed {
+                return Ok(errors::EXIT_CONNECTION_FAILED);
+            }
+        }
+        Commands::Schedule(schedule_args) => {
+            // The configuration is read again on SIGHUP and runs are recorded
+            // after a daemon has moved into its working directory.
+            let config_path = config::absolute_config_path(args.config)?;
+            let schedule_args = schedule_args.absolute_history_file()?;
+            let load_jobs = || resolve_schedule_jobs(schedule_args.clone(), config_path.clone());
+            let jobs = load_jobs()?;
+            match schedule_args.preview() {
+                Some(count) => {
+                    let previews = borg::schedule::preview_schedule(&jobs, count)?;
+                    match output {
+                        util::OutputFormat::Text => {
+                            borg::schedule::display_schedule_preview(previews)
+                        }
+                        util::OutputFormat::Json => util::json_print(&previews),
+                    }
+                }
+                None => borg::schedule::schedule_borg(jobs, load_jobs)?,
+            }
+        }
+        Commands::Systemd(systemd_args) => {
+            let jobs = resolve_schedule_jobs(systemd_args.schedule(), args.config)?;
+            let units = borg::systemd::generate_systemd_units(&systemd_args, jobs)?;
+            borg::systemd::install_systemd_units(&systemd_args, units)?;
+        }
+        Commands::History(history_args) => {
+            let records = history::list_history(&history_args)?;
+            match output {
+                util::OutputFormat::Text => history::display_history(records),
+                util::OutputFormat::Json => util::json_print(&records),
             }
         }
-        Commands::Schedule(schedule_args) => borg::schedule::schedule_borg(&schedule_args),
+    }
+    Ok(errors::EXIT_SUCCESS)
+}
+
+fn main() {
+    env_logger::init();
+    let args = Args::parse();
+    let output = args.output;
+    util::set_output_format(output);
+
+    match run(args) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            report_error(output, &e);
+            process::exit(e.exit_code());
+        }
     }
 }
@@ -1,26 +1,12 @@
-use clap::Parser;
+use clap::ValueEnum;
 use log::{error, info, warn};
 use rand::{distributions::Alphanumeric, Rng};
-use ssh2::Error;
-use ssh2::Session;
+use serde::Serialize;
 use std::env;
-use std::net::TcpStream;
-use std::path::Path;
-use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tempfile;
 
-const KEY_PATH: &str = "./keys/id_rsa";
-
-// Struct for managing the necessary arguments for verifying an SSH connection.
-#[derive(Debug, Clone, Parser)]
-pub struct VerifyArgs {
-    user: String,
-    host: String,
-    #[arg(short, long, default_value_t = 22)]
-    port: u32,
-    #[arg(short, long, default_value_t = String::from(KEY_PATH))]
-    key_file: String,
-}
+pub mod verify;
 
 pub enum LogLevel {
     Info,
@@ -28,37 +14,30 @@ pub enum LogLevel {
     Error,
 }
 
-// Verifies an SSH connection with options defined in the VerifyArgs parameter.
-//
-// If an error occurs then the error will be propagated to the caller.
-pub fn verify_connection(verify_args: VerifyArgs) -> Result<bool, Error> {
-    let host_port: String = format!("{}:{}", verify_args.host, verify_args.port);
-
-    let tcp = match TcpStream::connect(&host_port) {
-        Ok(n) => n,
-        Err(e) => {
-            eprintln!("Error creating TCP stream: {}", e);
-            process::exit(1);
-        }
-    };
-
-    let key_path = Path::new(&verify_args.key_file);
-
-    let mut sess = Session::new()?;
-    sess.set_tcp_stream(tcp);
-    sess.handshake()?;
-    sess.userauth_pubkey_file(&verify_args.user, None, key_path, None)?;
-
-    Ok(sess.authenticated())
+// The format command results and errors are written to the `stdout` in.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
 }
 
-fn exiterr_with_message(code: i32, message: &str) {
-    log_print(message, LogLevel::Error);
-    process::exit(code);
+// Set when the output format is JSON, so messages are kept out of the
+// JSON documents written to the `stdout`.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_output_format(output: OutputFormat) {
+    JSON_OUTPUT.store(output == OutputFormat::Json, Ordering::SeqCst);
 }
 
+// Prints a message, or logs it at the given level when `RUST_LOG` is set.
+//
+// Errors are written to the `stderr` and never exit the process, the
+// exit status is decided by `main` so it's the same with or without `RUST_LOG`.
+// With JSON output every message goes to the `stderr`, leaving the
+// `stdout` to the JSON documents.
 pub fn log_print(message: &str, level: LogLevel) {
-    if let Ok(_) = env::var("RUST_LOG") {
+    if env::var("RUST_LOG").is_ok() {
         match level {
             LogLevel::Info => info!("{}", message),
             LogLevel::Warn => warn!("{}", message),
@@ -66,12 +45,43 @@ pub fn log_print(message: &str, level: LogLevel) {
         }
     } else {
         match level {
-            LogLevel::Error => exiterr_with_message(1, &format!("{}", message)),
+            LogLevel::Error => eprintln!("{}", message),
+            _ if JSON_OUTPUT.load(Ordering::SeqCst) => eprintln!("{}", message),
             _ => println!("{}", message),
         }
     }
 }
 
+// Prints a value as a single line JSON document to the `stdout`.
+pub fn json_print(value: &impl Serialize) {
+    match serde_json::to_string(value) {
+        Ok(n) => println!("{}", n),
+        Err(e) => json_error_print(
+            &format!("Failed to serialise output: {}", e),
+            "serialise_failed",
+            crate::errors::EXIT_ERROR,
+        ),
+    }
+}
+
+// The JSON document for an error, with the kind of error and the exit code
+// so scripts can branch on them without parsing the message.
+fn json_error(message: &str, kind: &str, exit_code: i32) -> serde_json::Value {
+    serde_json::json!({
+        "error": {
+            "message": message,
+            "kind": kind,
+            "exit_code": exit_code,
+        }
+    })
+}
+
+// Prints an error as a JSON document to the `stdout`, so the output
+// of a failed command can still be parsed.
+pub fn json_error_print(message: &str, kind: &str, exit_code: i32) {
+    println!("{}", json_error(message, kind, exit_code));
+}
+
 #[allow(dead_code)]
 pub fn get_temp_directory() -> String {
     tempfile::tempdir()
@@ -90,3 +100,22 @@ pub fn get_random_string(length: usize) -> String {
         .map(char::from)
         .collect()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_json_error() {
+        let result = json_error("Operation failed", "lock_held", 5);
+        assert_eq!(result["error"]["message"], "Operation failed");
+        assert_eq!(result["error"]["kind"], "lock_held");
+        assert_eq!(result["error"]["exit_code"], 5);
+    }
+
+    #[test]
+    fn test_log_print_error_returns() {
+        log_print("message", LogLevel::Error);
+        log_print("message", LogLevel::Warn);
+    }
+}
@@ -0,0 +1,258 @@
+use super::super::borg::errors::{PassphraseError, RemoteError};
+use super::super::borg::passphrase::{self, KeyPassphraseArgs};
+use super::super::borg::remote::{self, Credentials, HostKeyPolicy, HostKeyStatus};
+use super::{log_print, LogLevel};
+use clap::Parser;
+use serde::Serialize;
+use std::path::PathBuf;
+
+// Struct for managing the necessary arguments for verifying an SSH connection.
+#[derive(Debug, Clone, Parser)]
+pub struct VerifyArgs {
+    user: String,
+    host: String,
+    #[arg(short, long, default_value_t = 22)]
+    port: u16,
+    #[arg(short, long, value_name = "FILE")]
+    key_file: Option<String>,
+    #[arg(long, default_value_t = false)]
+    no_agent: bool,
+    #[command(flatten)]
+    key_passphrase_source: KeyPassphraseArgs,
+    #[arg(long, value_name = "VARIABLE", conflicts_with = "password_prompt")]
+    password_env: Option<String>,
+    #[arg(long, default_value_t = false)]
+    password_prompt: bool,
+    #[arg(short, long, value_name = "PATH")]
+    repository: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    remote_path: Option<String>,
+    #[arg(long, value_name = "FILE")]
+    known_hosts: Option<String>,
+    #[arg(long, value_enum, default_value_t = HostKeyPolicy::Strict)]
+    host_key_policy: HostKeyPolicy,
+}
+
+// The outcome of a single step of the verification.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyCheck {
+    pub name: String,
+    pub passed: bool,
+    pub message: String,
+}
+
+// The checks run against the host, in order. Verification stops at the
+// first check that fails, as the later ones depend on it.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyResult {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub passed: bool,
+    pub host_key_fingerprint: Option<String>,
+    pub auth_method: Option<String>,
+    pub borg_version: Option<String>,
+    pub checks: Vec<VerifyCheck>,
+}
+
+impl VerifyResult {
+    fn new(verify_args: &VerifyArgs) -> VerifyResult {
+        VerifyResult {
+            host: verify_args.host.to_owned(),
+            port: verify_args.port,
+            user: verify_args.user.to_owned(),
+            passed: true,
+            host_key_fingerprint: None,
+            auth_method: None,
+            borg_version: None,
+            checks: Vec::new(),
+        }
+    }
+
+    // Records the outcome of a check, returning the value if it passed.
+    fn record<T>(
+        &mut self,
+        name: &str,
+        outcome: Result<T, RemoteError>,
+        message: impl Fn(&T) -> String,
+    ) -> Option<T> {
+        let (passed, message, value) = match outcome {
+            Ok(n) => (true, message(&n), Some(n)),
+            Err(e) => (false, e.to_string(), None),
+        };
+        self.passed &= passed;
+        self.checks.push(VerifyCheck {
+            name: name.to_owned(),
+            passed,
+            message,
+        });
+        value
+    }
+}
+
+impl VerifyArgs {
+    // The credentials to authenticate with, reading the key passphrase and
+    // password from their sources. Without a key file the default
+    // identities of `~/.ssh` are tried.
+    pub fn credentials(&self) -> Result<Credentials, PassphraseError> {
+        let mut credentials = Credentials::new(self.key_file.as_deref());
+        credentials.use_agent = !self.no_agent;
+        credentials.key_passphrase = self.key_passphrase_source.read()?;
+        credentials.password = passphrase::read_source(
+            self.password_env.as_deref(),
+            None,
+            None,
+            self.password_prompt.then_some("Enter SSH password: "),
+        )?;
+        Ok(credentials)
+    }
+}
+
+// Verifies an SSH connection with options defined in the VerifyArgs parameter.
+//
+// The host key is checked against the known hosts file before logging in.
+// Beyond logging in, borg must be installed on the host with a supported
+// version, and when a repository path is given it must be writable.
+pub fn verify_connection(verify_args: &VerifyArgs, credentials: &Credentials) -> VerifyResult {
+    let mut result = VerifyResult::new(verify_args);
+    let (host, port) = (verify_args.host.as_str(), verify_args.port);
+
+    let Some(tcp) = result.record("connect", remote::connect(host, port), |_| {
+        format!("Connected to {}:{}", host, port)
+    }) else {
+        return result;
+    };
+    let Some(session) = result.record("handshake", remote::handshake(tcp), |_| {
+        "SSH session established".to_owned()
+    }) else {
+        return result;
+    };
+
+    let known_hosts = verify_args
+        .known_hosts
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(remote::default_known_hosts);
+    let host_key = remote::check_host_key(
+        &session,
+        host,
+        port,
+        &known_hosts,
+        verify_args.host_key_policy,
+    );
+    let Some(host_key) = result.record("host_key", host_key, |n| match n.status {
+        HostKeyStatus::Known => format!(
+            "Host key {} matches {}",
+            n.fingerprint,
+            known_hosts.display()
+        ),
+        HostKeyStatus::Added => format!(
+            "Host key {} added to {}",
+            n.fingerprint,
+            known_hosts.display()
+        ),
+        HostKeyStatus::Unverified => format!(
+            "Host key {} is not in {} and was not verified",
+            n.fingerprint,
+            known_hosts.display()
+        ),
+    }) else {
+        return result;
+    };
+    result.host_key_fingerprint = Some(host_key.fingerprint);
+
+    let authenticated = remote::authenticate(&session, host, &verify_args.user, credentials);
+    let Some(auth_method) = result.record("authenticate", authenticated, |n| {
+        format!("Authenticated as {} with {}", verify_args.user, n)
+    }) else {
+        return result;
+    };
+    result.auth_method = Some(auth_method.to_string());
+
+    let remote_path = verify_args
+        .remote_path
+        .to_owned()
+        .unwrap_or(remote::DEFAULT_REMOTE_PATH.to_owned());
+    let borg_version = remote::remote_borg_version(&session, host, &remote_path);
+    result.borg_version = result.record("borg", borg_version, |n| format!("Found borg {}", n));
+    if result.borg_version.is_none() {
+        return result;
+    }
+
+    if let Some(path) = &verify_args.repository {
+        let writable = remote::check_writable(&session, host, path);
+        result.record("repository", writable, |_| format!("{} is writable", path));
+    }
+    result
+}
+
+pub fn display_verify_info(verify_result: &VerifyResult) {
+    verify_result.checks.iter().for_each(|check| {
+        let status = if check.passed { "PASS" } else { "FAIL" };
+        let level = if check.passed {
+            LogLevel::Info
+        } else {
+            LogLevel::Error
+        };
+        log_print(
+            &format!("[{}] {}: {}", status, check.name, check.message),
+            level,
+        );
+    });
+
+    match verify_result.passed {
+        true => log_print("Connection verified!", LogLevel::Info),
+        false => log_print("Connection verification failed", LogLevel::Error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup_verify_args(port: u16) -> VerifyArgs {
+        VerifyArgs {
+            user: "user".to_owned(),
+            host: "127.0.0.1".to_owned(),
+            port,
+            key_file: None,
+            no_agent: false,
+            key_passphrase_source: KeyPassphraseArgs::default(),
+            password_env: None,
+            password_prompt: false,
+            repository: None,
+            remote_path: None,
+            known_hosts: None,
+            host_key_policy: HostKeyPolicy::Strict,
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let mut result = VerifyResult::new(&setup_verify_args(22));
+        let value = result.record("first", Ok(1), |n| format!("value {}", n));
+        assert_eq!(value, Some(1));
+        assert!(result.passed);
+
+        let error = RemoteError::AuthenticationFailed("user@host".to_owned(), Vec::new());
+        let value: Option<i32> = result.record("second", Err(error), |n| n.to_string());
+        assert_eq!(value, None);
+        assert!(!result.passed);
+        assert_eq!(result.checks.len(), 2);
+        assert_eq!(result.checks[0].message, "value 1");
+        assert!(!result.checks[1].passed);
+    }
+
+    #[test]
+    fn test_verify_connection_refused() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let result = verify_connection(&setup_verify_args(port), &Credentials::default());
+        assert!(!result.passed);
+        assert_eq!(result.checks.len(), 1);
+        assert_eq!(result.checks[0].name, "connect");
+        assert_eq!(result.borg_version, None);
+    }
+}